@@ -0,0 +1,182 @@
+//! `#[derive(Pack, Unpack)]` for structs whose fields carry a `#[pack("...")]` format.
+use proc_macro::{Delimiter, Span, TokenStream, TokenTree};
+
+use crate::{compile_error, string_literal, Template};
+
+pub(crate) enum Kind {
+    Pack,
+    Unpack,
+}
+
+struct Record {
+    name: String,
+    fields: Vec<Field>,
+    tuple: bool,
+}
+
+struct Field {
+    /// Field name, or its index for tuple structs.
+    member: String,
+    format: String,
+}
+
+pub(crate) fn expand(input: TokenStream, kind: Kind) -> TokenStream {
+    let record = match parse(input) {
+        Ok(record) => record,
+        Err((message, span)) => return compile_error(&message, span),
+    };
+    // a field format may end with a `#` comment, which would swallow the formats after it on the same line
+    let template = record.fields.iter().map(|f| f.format.as_str()).collect::<Vec<_>>().join("\n");
+    let code = match kind {
+        Kind::Pack => {
+            let args = record
+                .fields
+                .iter()
+                .map(|f| format!("::rust_pack::PackableArg::from(&self.{})", f.member))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "impl {name} {{ \
+                    /// Template made of the `#[pack]` formats of every field. \n\
+                    pub const TEMPLATE: &'static str = {template:?}; \
+                }} \
+                impl ::rust_pack::Pack for {name} {{ \
+                    const TEMPLATE: &'static str = {template:?}; \
+                    fn pack(&self) -> ::core::result::Result<::rust_pack::Packed, ::rust_pack::PackError> {{ \
+                        ::rust_pack::pack(<Self as ::rust_pack::Pack>::TEMPLATE, [{args}].into_iter()) \
+                    }} \
+                }}",
+                name = record.name,
+            )
+        }
+        Kind::Unpack => {
+            let values = record
+                .fields
+                .iter()
+                .map(|f| match record.tuple {
+                    true => "__values.next_value()?".to_string(),
+                    false => format!("{}: __values.next_value()?", f.member),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let constructor = match record.tuple {
+                true => format!("Self({})", values),
+                false => format!("Self {{ {} }}", values),
+            };
+            format!(
+                "impl ::rust_pack::Unpack for {name} {{ \
+                    const TEMPLATE: &'static str = {template:?}; \
+                    fn unpack(data: &[u8]) -> ::core::result::Result<Self, ::rust_pack::UnpackError> {{ \
+                        let mut __values = ::rust_pack::__private::Values::unpack(<Self as ::rust_pack::Unpack>::TEMPLATE, data)?; \
+                        ::core::result::Result::Ok({constructor}) \
+                    }} \
+                }}",
+                name = record.name,
+            )
+        }
+    };
+    code.parse().unwrap()
+}
+
+fn parse(input: TokenStream) -> Result<Record, (String, Span)> {
+    let mut tokens = input.into_iter().peekable();
+    skip_attributes_and_visibility(&mut tokens);
+    match tokens.next() {
+        Some(TokenTree::Ident(i)) if i.to_string() == "struct" => {}
+        Some(tree) => return Err(("only structs can derive Pack and Unpack".to_string(), tree.span())),
+        None => return Err(("expected a struct".to_string(), Span::call_site())),
+    }
+    let name = match tokens.next() {
+        Some(TokenTree::Ident(i)) => i,
+        _ => return Err(("expected a struct name".to_string(), Span::call_site())),
+    };
+    let (body, tuple) = match tokens.next() {
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace => (g, false),
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis => (g, true),
+        Some(TokenTree::Punct(p)) if p.as_char() == '<' => {
+            return Err(("generic structs are not supported".to_string(), p.span()));
+        }
+        _ => return Err(("struct has no fields to pack".to_string(), name.span())),
+    };
+    let mut fields = Vec::new();
+    for (index, field) in split_fields(body.stream()).into_iter().enumerate() {
+        let mut tokens = field.into_iter().peekable();
+        let mut format = None;
+        while let Some(TokenTree::Punct(p)) = tokens.peek() {
+            if p.as_char() != '#' {
+                break;
+            }
+            tokens.next();
+            if let Some(TokenTree::Group(attribute)) = tokens.next() {
+                let mut attribute = attribute.stream().into_iter();
+                match (attribute.next(), attribute.next()) {
+                    (Some(TokenTree::Ident(i)), Some(TokenTree::Group(args))) if i.to_string() == "pack" => {
+                        let args = args.stream().into_iter().collect::<Vec<_>>();
+                        format = Some(string_literal(&args).ok_or(("expected #[pack(\"<format>\")]".to_string(), i.span()))?);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        skip_attributes_and_visibility(&mut tokens);
+        let member = match tuple {
+            true => index.to_string(),
+            false => match tokens.next() {
+                Some(TokenTree::Ident(i)) => i.to_string(),
+                _ => return Err(("expected a field name".to_string(), body.span())),
+            },
+        };
+        let (format, span) = format.ok_or(format!("field `{}` is missing a #[pack(\"<format>\")] attribute", member))
+            .map_err(|message| (message, name.span()))?;
+        match Template::parse(&format).and_then(|t| t.value_types()) {
+            Ok(types) if types.len() == 1 => {}
+            Ok(_) => return Err((format!("format `{}` must produce exactly one value", format), span)),
+            Err(e) => return Err((e.render(&format), span)),
+        }
+        fields.push(Field { member, format });
+    }
+    if fields.is_empty() {
+        return Err(("struct has no fields to pack".to_string(), name.span()));
+    }
+    Ok(Record { name: name.to_string(), fields, tuple })
+}
+
+fn skip_attributes_and_visibility(tokens: &mut std::iter::Peekable<impl Iterator<Item=TokenTree>>) {
+    loop {
+        match tokens.peek() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '#' => {
+                tokens.next();
+                tokens.next();
+            }
+            Some(TokenTree::Ident(i)) if i.to_string() == "pub" => {
+                tokens.next();
+                if let Some(TokenTree::Group(g)) = tokens.peek() {
+                    if g.delimiter() == Delimiter::Parenthesis {
+                        tokens.next();
+                    }
+                }
+            }
+            _ => return,
+        }
+    }
+}
+
+/// Splits a struct body on top level commas, commas between angle brackets belong to types.
+fn split_fields(body: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut fields = vec![Vec::new()];
+    let mut depth = 0usize;
+    for tree in body {
+        match &tree {
+            TokenTree::Punct(p) if p.as_char() == ',' && depth == 0 => {
+                fields.push(Vec::new());
+                continue;
+            }
+            TokenTree::Punct(p) if p.as_char() == '<' => depth += 1,
+            TokenTree::Punct(p) if p.as_char() == '>' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        fields.last_mut().unwrap().push(tree);
+    }
+    fields.retain(|f| !f.is_empty());
+    fields
+}
@@ -0,0 +1,197 @@
+//! Procedural macros behind `rust_pack`'s `pack!`, `unpack!` and `#[derive(Pack, Unpack)]`.
+//!
+//! Literal templates are parsed while compiling, so a typo in a template is a compile error
+//! instead of a runtime `PackError`.
+//!
+//! This crate is an implementation detail, use the macros re-exported by `rust_pack` instead.
+//! Its only dependency is the template parser of `rust_pack`, so the token handling is done directly on [`proc_macro`] types.
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+mod derive;
+mod template;
+
+use template::Template;
+
+/// `pack_checked!($crate, "template", args...)`, checks the template and the number of arguments
+/// and expands into a `rust_pack::pack` call.
+#[doc(hidden)]
+#[proc_macro]
+pub fn pack_checked(input: TokenStream) -> TokenStream {
+    let mut args = split_arguments(input).into_iter();
+    let (krate, template) = match (args.next(), args.next()) {
+        (Some(krate), Some(template)) => (krate, template),
+        _ => return compile_error("expected a template literal", Span::call_site()),
+    };
+    let args = args.collect::<Vec<_>>();
+    let (template, span) = match string_literal(&template) {
+        Some(t) => t,
+        None => return compile_error("template must be a string literal", Span::call_site()),
+    };
+    match Template::parse(&template).and_then(|t| t.argument_count()) {
+        Ok((count, false)) if count != args.len() => {
+            let message = format!("template {:?} expects {} arguments, got {}", template, count, args.len());
+            return compile_error(&message, span);
+        }
+        Ok((count, true)) if count > args.len() => {
+            let message = format!("template {:?} expects at least {} arguments, got {}", template, count, args.len());
+            return compile_error(&message, span);
+        }
+        Ok(_) => {}
+        Err(e) => return compile_error(&e.render(&template), span),
+    }
+    let code = format!(
+        "__rust_pack_crate::pack({:?}, [{}].into_iter())",
+        template,
+        (0..args.len()).map(|i| format!("__rust_pack_crate::PackableArg::from(__rust_pack_arg{})", i)).collect::<Vec<_>>().join(", ")
+    );
+    substitute(code.parse().unwrap(), &krate, &args)
+}
+
+/// `unpack_typed!($crate, "template", data)`, expands into an expression evaluating to
+/// `Result<(T1, T2, ...), UnpackError>` where every `T` is inferred from its format character.
+#[doc(hidden)]
+#[proc_macro]
+pub fn unpack_typed(input: TokenStream) -> TokenStream {
+    let args = split_arguments(input);
+    let [krate, template, data] = match <[Vec<TokenTree>; 3]>::try_from(args) {
+        Ok(args) => args,
+        Err(_) => return compile_error("expected a template literal and the data to unpack", Span::call_site()),
+    };
+    let (template, span) = match string_literal(&template) {
+        Some(t) => t,
+        None => return compile_error("template must be a string literal", Span::call_site()),
+    };
+    let types = match Template::parse(&template).and_then(|t| t.value_types()) {
+        Ok(types) => types,
+        Err(e) => return compile_error(&e.render(&template), span),
+    };
+    let values = types
+        .iter()
+        .map(|t| format!("__values.next_value::<{}>()", t))
+        .collect::<Vec<_>>();
+    let result = match values.len() {
+        1 => values[0].clone(),
+        _ => format!("::core::result::Result::Ok(({}?))", values.join("?, ")),
+    };
+    let code = format!(
+        "__rust_pack_crate::__private::Values::unpack({:?}, ::core::convert::AsRef::<[u8]>::as_ref(&__rust_pack_data)).and_then(|mut __values| {{ \
+            {} \
+        }})",
+        template, result
+    );
+    substitute(code.parse().unwrap(), &krate, &[data])
+}
+
+/// Implements `rust_pack::Pack` and adds an inherent `TEMPLATE` constant to the struct.
+#[proc_macro_derive(Pack, attributes(pack))]
+pub fn derive_pack(input: TokenStream) -> TokenStream {
+    derive::expand(input, derive::Kind::Pack)
+}
+
+/// Implements `rust_pack::Unpack`.
+#[proc_macro_derive(Unpack, attributes(pack))]
+pub fn derive_unpack(input: TokenStream) -> TokenStream {
+    derive::expand(input, derive::Kind::Unpack)
+}
+
+/// Splits the macro input on top level commas, looking through invisible groups.
+fn split_arguments(input: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut args = vec![Vec::new()];
+    for tree in input {
+        match tree {
+            TokenTree::Punct(p) if p.as_char() == ',' => args.push(Vec::new()),
+            tree => args.last_mut().unwrap().push(tree),
+        }
+    }
+    if args.last().is_some_and(|a| a.is_empty()) {
+        args.pop();
+    }
+    args
+}
+
+/// Value and span of a (possibly raw) string literal.
+fn string_literal(tokens: &[TokenTree]) -> Option<(String, Span)> {
+    match tokens {
+        [TokenTree::Group(g)] if g.delimiter() == Delimiter::None => {
+            string_literal(&g.stream().into_iter().collect::<Vec<_>>())
+        }
+        [TokenTree::Literal(l)] => unquote(&l.to_string()).map(|s| (s, l.span())),
+        _ => None,
+    }
+}
+
+/// Value of a string literal as the compiler reads it, `None` for other literals and escapes it doesn't know.
+fn unquote(literal: &str) -> Option<String> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        return raw.get(hashes + 1..raw.len() - hashes - 1).map(str::to_string);
+    }
+    let inner = literal.strip_prefix('"')?.strip_suffix('"')?;
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => result.push('\n'),
+            't' => result.push('\t'),
+            'r' => result.push('\r'),
+            '0' => result.push('\0'),
+            c @ ('\\' | '"' | '\'') => result.push(c),
+            'x' => {
+                let digits = chars.as_str().get(..2).filter(|d| d.bytes().all(|b| b.is_ascii_hexdigit()))?;
+                result.push(char::from(u8::from_str_radix(digits, 16).ok().filter(u8::is_ascii)?));
+                chars.nth(1);
+            }
+            'u' => {
+                let (digits, rest) = chars.as_str().strip_prefix('{')?.split_once('}')?;
+                let digits = digits.replace('_', "");
+                if digits.is_empty() || digits.len() > 6 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return None;
+                }
+                result.push(char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?);
+                chars = rest.chars();
+            }
+            '\n' => {
+                while chars.clone().next().is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r')) {
+                    chars.next();
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(result)
+}
+
+/// Replaces the placeholders of generated code: `__rust_pack_crate` with the path of `rust_pack`,
+/// and `__rust_pack_data` (or `__rust_pack_arg<N>` when there are many) with the caller's expressions.
+fn substitute(code: TokenStream, krate: &[TokenTree], args: &[Vec<TokenTree>]) -> TokenStream {
+    code.into_iter()
+        .flat_map(|tree| match tree {
+            TokenTree::Ident(ref i) if i.to_string() == "__rust_pack_crate" => krate.to_vec(),
+            TokenTree::Ident(ref i) if i.to_string() == "__rust_pack_data" => {
+                vec![TokenTree::Group(Group::new(Delimiter::Parenthesis, args[0].iter().cloned().collect()))]
+            }
+            TokenTree::Ident(ref i) if i.to_string().starts_with("__rust_pack_arg") => {
+                let index = i.to_string()["__rust_pack_arg".len()..].parse::<usize>().unwrap();
+                vec![TokenTree::Group(Group::new(Delimiter::Parenthesis, args[index].iter().cloned().collect()))]
+            }
+            TokenTree::Group(g) => {
+                let mut group = Group::new(g.delimiter(), substitute(g.stream(), krate, args));
+                group.set_span(g.span());
+                vec![TokenTree::Group(group)]
+            }
+            tree => vec![tree],
+        })
+        .collect()
+}
+
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut group = Group::new(Delimiter::Parenthesis, TokenTree::Literal(Literal::string(message)).into());
+    group.set_span(span);
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    [Ident::new("compile_error", span).into(), bang.into(), TokenTree::Group(group)].into_iter().collect()
+}
@@ -0,0 +1,184 @@
+//! Compile time view of templates, parsed by `rust_pack_syntax` the same way `rust_pack` parses them at runtime.
+use std::ops::Range;
+
+use rust_pack_syntax::{nodes, parse, Count, PackError, PackType, TemplateError};
+
+/// A template the macros reject, with where: an invalid template, or a valid one which only works
+/// with the functions, like `%` in `pack!`.
+pub(crate) struct Rejection {
+    message: &'static str,
+    /// Byte offset of the offending part of the template.
+    offset: usize,
+    snippet: String,
+}
+
+impl Rejection {
+    /// The message followed by the template with the offending part underlined.
+    pub(crate) fn render(&self, template: &str) -> String {
+        let column = template[..self.offset].chars().count();
+        let width = self.snippet.chars().count().max(1);
+        format!("{} at offset {}\n  {}\n  {}{}", self.message, self.offset, template, " ".repeat(column), "^".repeat(width))
+    }
+}
+
+impl From<TemplateError> for Rejection {
+    fn from(e: TemplateError) -> Self {
+        Rejection { message: message(&e.error), offset: e.offset, snippet: e.snippet }
+    }
+}
+
+/// What is wrong with a template the parser returned `error` for.
+fn message(error: &PackError) -> &'static str {
+    match error {
+        PackError::InvalidFormatLengthArgument => "invalid count, `[...]` must hold a number or a template of a size known while compiling",
+        PackError::EmptyFormatCharacter => "modifier, count, `/` or `%` without its format character",
+        PackError::InvalidFormatCharacter => "format character is not supported",
+        PackError::InvalidFormatModifier => "modifier is not supported by the format character",
+        PackError::EmptyTemplate => "template is empty",
+        PackError::UnbalancedParentheses => "group parentheses are not balanced",
+        PackError::InvalidLengthItem => "format cannot hold a length before `/` or take one after it",
+        PackError::InvalidChecksum => "`%` must be followed by a numeric format, a character or a bit string",
+        _ => "template is not valid",
+    }
+}
+
+/// A template with where its items are, see `rust_pack::Template`.
+pub(crate) struct Template<'a> {
+    source: &'a str,
+    items: Vec<PackType>,
+    spans: Vec<Range<usize>>,
+}
+
+impl<'a> Template<'a> {
+    /// Parses the template like `rust_pack` does, but for the sizes of native formats in `[template]` counts,
+    /// which depend on the target.
+    pub(crate) fn parse(source: &'a str) -> Result<Template<'a>, Rejection> {
+        let (items, spans) = parse(source, None)?;
+        Ok(Template { source, items, spans })
+    }
+
+    /// Number of arguments `pack` consumes, and whether it takes any number of arguments more.
+    pub(crate) fn argument_count(&self) -> Result<(usize, bool), Rejection> {
+        self.arguments(&self.items, 0)
+    }
+
+    /// Rust type of every value produced by the template, in order.
+    pub(crate) fn value_types(&self) -> Result<Vec<&'static str>, Rejection> {
+        let mut types = Vec::new();
+        self.types(&self.items, 0, &mut types)?;
+        Ok(types)
+    }
+
+    fn unsupported(&self, message: &'static str, index: usize) -> Rejection {
+        let span = self.spans[index].clone();
+        Rejection { message, offset: span.start, snippet: self.source[span].to_string() }
+    }
+
+    /// Counts the arguments of `items`, the first of them being at `index` in the spans.
+    fn arguments(&self, items: &[PackType], mut index: usize) -> Result<(usize, bool), Rejection> {
+        let mut count = 0;
+        let mut unbounded = false;
+        for item in items {
+            match item {
+                PackType::Checksum(..) => return Err(self.unsupported("`%` checksums only work in unpack", index)),
+                PackType::LengthPrefixed(_, item) => match &**item {
+                    PackType::NullByte(_) => {}
+                    p if p.is_string() => count += 1,
+                    // packs its count or less, when arguments run out
+                    _ => unbounded = true,
+                },
+                PackType::Group(items, Count::Exact(n)) => {
+                    let (group_count, group_unbounded) = self.arguments(items, index + 1)?;
+                    count += group_count * n;
+                    unbounded |= group_unbounded && *n > 0;
+                }
+                // the group repeats for as long as arguments are left
+                PackType::Group(_, Count::Star) => unbounded = true,
+                PackType::CharacterMode | PackType::ByteMode | PackType::NullByte(_) => {}
+                // the count of `.` tells where the position is counted from
+                PackType::ValuePosition(_) | PackType::Uuencoded(_) => count += 1,
+                p if p.is_position() => {}
+                p if p.is_string() => count += 1,
+                p => match p.count() {
+                    Count::Exact(n) => count += n,
+                    Count::Star => unbounded = true,
+                },
+            }
+            index += nodes(item);
+        }
+        Ok((count, unbounded))
+    }
+
+    /// Adds the types of the values of `items` to `types`, the first of them being at `index` in the spans.
+    fn types(&self, items: &[PackType], mut index: usize, types: &mut Vec<&'static str>) -> Result<(), Rejection> {
+        for item in items {
+            match item {
+                PackType::LengthPrefixed(_, item) => match &**item {
+                    PackType::NullByte(_) => {}
+                    p if p.is_string() => types.push(value_type(p)),
+                    _ => {
+                        let message = "the length before `/` makes the number of values variable, use `unpack()` instead";
+                        return Err(self.unsupported(message, index));
+                    }
+                },
+                PackType::Checksum(bits, item) => {
+                    let float = matches!(**item, PackType::Float(..) | PackType::Double(..) | PackType::PerlFloat(..) | PackType::LongDouble(..));
+                    types.push(if *bits > 64 || float { "f64" } else { "u64" });
+                }
+                PackType::Group(items, Count::Exact(n)) => {
+                    let mut group_types = Vec::new();
+                    self.types(items, index + 1, &mut group_types)?;
+                    types.extend((0..*n).flat_map(|_| group_types.iter().copied()));
+                }
+                PackType::Group(_, Count::Star) => {
+                    return Err(self.unsupported("`(...)*` produces a variable number of values, use `unpack()` instead", index));
+                }
+                PackType::CharacterMode | PackType::ByteMode | PackType::NullByte(_) => {}
+                p @ (PackType::ValuePosition(_) | PackType::Uuencoded(_)) => types.push(value_type(p)),
+                p if p.is_position() => {}
+                p if p.is_string() => types.push(value_type(p)),
+                p => match p.count() {
+                    Count::Exact(n) => types.extend(std::iter::repeat_n(value_type(p), n)),
+                    Count::Star => return Err(self.unsupported("`*` produces a variable number of values, use `unpack()` instead", index)),
+                },
+            }
+            index += nodes(item);
+        }
+        Ok(())
+    }
+}
+
+/// Rust type of the values of a format producing some.
+fn value_type(pack_type: &PackType) -> &'static str {
+    match pack_type {
+        PackType::StringNullPadded(_) | PackType::Uuencoded(_) => "__rust_pack_crate::__private::Vec<u8>",
+        PackType::AsciiNullPadded(_)
+        | PackType::AscizNullPadded(_)
+        | PackType::BitStringAscending(_)
+        | PackType::BitStringDescending(_)
+        | PackType::HexStringLowFirst(_)
+        | PackType::HexStringHighFirst(_) => "__rust_pack_crate::__private::String",
+        PackType::SignedChar(_) => "i8",
+        PackType::UnsignedChar(_) => "u8",
+        PackType::WideChar(_) | PackType::UnicodeChar(_) => "char",
+        PackType::SignedShort(..) | PackType::SignedShortBE(_) | PackType::SignedShortLE(_) => "i16",
+        PackType::UnsignedShort(..) | PackType::UnsignedShortBE(_) | PackType::UnsignedShortLE(_) => "u16",
+        PackType::SignedLong(..) | PackType::SignedLongBE(_) | PackType::SignedLongLE(_) => "i32",
+        PackType::UnsignedLong(..) | PackType::UnsignedLongBE(_) | PackType::UnsignedLongLE(_) => "u32",
+        PackType::SignedQuad(..) => "i64",
+        PackType::UnsignedQuad(..) => "u64",
+        PackType::NativeSignedShort(..) => "::core::ffi::c_short",
+        PackType::NativeUnsignedShort(..) => "::core::ffi::c_ushort",
+        PackType::SignedInteger(..) => "::core::ffi::c_int",
+        PackType::UnsignedInteger(..) => "::core::ffi::c_uint",
+        PackType::NativeSignedLong(..) => "::core::ffi::c_long",
+        PackType::NativeUnsignedLong(..) => "::core::ffi::c_ulong",
+        PackType::PerlSignedInteger(..) => "isize",
+        PackType::PerlUnsignedInteger(..) | PackType::ValuePosition(_) => "usize",
+        PackType::Float(..) => "f32",
+        PackType::Double(..) | PackType::PerlFloat(..) | PackType::LongDouble(..) => "f64",
+        PackType::BerCompressed(_) | PackType::UnsignedLeb128(_) => "u128",
+        PackType::SignedLeb128(_) | PackType::ZigZagVarint(_) => "i128",
+        p => unreachable!("{:?} produces no value", p),
+    }
+}
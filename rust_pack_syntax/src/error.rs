@@ -0,0 +1,189 @@
+//! [`PackError`], with where errors happen: the [`Location`] of an item in the template and in what it packs,
+//! and the [`TemplateError`] of a template which could not be parsed.
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use core::ops::Range;
+
+#[cfg(feature = "alloc")]
+use crate::PackType;
+
+/// Errors of the arguments come as [`PackError::Item`], telling the item and the argument which failed,
+/// see [`PackError::root_cause`] for the error itself.
+#[derive(Debug, Clone)]
+pub enum PackError {
+    LeftArgumentIsMissingForTemplate,
+    RightArgumentIsMissingForTemplate,
+    InvalidFormatLengthArgument,
+    EmptyFormatCharacter,
+    InvalidFormatCharacter,
+    InvalidFormatModifier,
+    EmptyTemplate,
+    NonFiniteInteger,
+    UnbalancedParentheses,
+    InvalidLengthItem,
+    PositionOutsideOfString,
+    InvalidChecksum,
+    NegativeCompressedInteger,
+    InvalidCharacter,
+    WideCharacter,
+    UnsupportedNativeSize,
+    BufferTooSmall,
+    /// The count of a string, bit string or hex string format is too large for the string to be allocated.
+    OutOfMemory,
+    /// Refusals of `Packable` implementations checking their values rather than converting them like Perl,
+    /// which the implementations of this crate never return: a number which doesn't fit in the format,
+    /// a string longer than the count of `a`, `A` or `Z`, and a value which the format can't take at all.
+    ValueOutOfRange,
+    StringTooLong,
+    WrongArgumentType,
+    /// The template could not be parsed, where and why.
+    #[cfg(feature = "alloc")]
+    Template(Box<TemplateError>),
+    /// An item of the template failed to pack: the item, where the packed string was,
+    /// the index of the argument it was packing if any, and why.
+    #[cfg(feature = "alloc")]
+    Item { at: Location, argument: Option<usize>, pack_type: Box<PackType>, cause: Box<PackError> },
+    /// An error of a `Packable` implementation, see [`PackError::other`].
+    #[cfg(feature = "alloc")]
+    Other(Arc<dyn Error + Send + Sync>),
+}
+
+impl PackError {
+    /// Wraps an error of a `Packable` implementation, which becomes the [`Error::source`] of the [`PackError`].
+    #[cfg(feature = "alloc")]
+    pub fn other<E: Error + Send + Sync + 'static>(error: E) -> PackError {
+        PackError::Other(Arc::new(error))
+    }
+
+    /// The error itself, without the item or the template position it happened in.
+    pub fn root_cause(&self) -> &PackError {
+        match self {
+            #[cfg(feature = "alloc")]
+            PackError::Item { cause, .. } => cause.root_cause(),
+            #[cfg(feature = "alloc")]
+            PackError::Template(e) => e.error.root_cause(),
+            e => e,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<TemplateError> for PackError {
+    fn from(e: TemplateError) -> Self {
+        PackError::Template(Box::new(e))
+    }
+}
+
+/// Errors of [`PackError::other`] are equal when they are the same error.
+impl PartialEq for PackError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            #[cfg(feature = "alloc")]
+            (PackError::Item { at, argument, pack_type, cause }, PackError::Item { at: a, argument: b, pack_type: p, cause: c }) =>
+                at == a && argument == b && pack_type == p && cause == c,
+            #[cfg(feature = "alloc")]
+            (PackError::Template(e), PackError::Template(o)) => e == o,
+            #[cfg(feature = "alloc")]
+            (PackError::Other(e), PackError::Other(o)) => Arc::ptr_eq(e, o),
+            _ => core::mem::discriminant(self) == core::mem::discriminant(other),
+        }
+    }
+}
+
+/// Where unpacking broke: in the data, and the template item reading it. Packing breaks the same way in the packed string.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Location {
+    /// Byte offset in the data where the item starts, or in the packed string for a [`PackError`].
+    pub offset: usize,
+    /// Index of the item in `Template::spans`.
+    pub item: usize,
+    /// Byte range of the item in the template.
+    pub span: Range<usize>,
+}
+
+/// A template which could not be parsed, with where in the source.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateError {
+    pub error: PackError,
+    /// Byte offset of the offending part of the template.
+    pub offset: usize,
+    /// The offending part of the template, usually the whole item.
+    pub snippet: String,
+}
+
+impl Display for PackError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "PackError: {}", match self {
+            #[cfg(feature = "alloc")]
+            PackError::Item { at, argument: Some(argument), cause, .. } => return write!(f, "{} with argument {} {}", cause, argument, at),
+            #[cfg(feature = "alloc")]
+            PackError::Item { at, argument: None, cause, .. } => return write!(f, "{} {}", cause, at),
+            #[cfg(feature = "alloc")]
+            PackError::Other(e) => return write!(f, "PackError: {}", e),
+            #[cfg(feature = "alloc")]
+            PackError::Template(e) => return write!(f, "{}", e),
+            PackError::LeftArgumentIsMissingForTemplate => "Template size is less then arguments count",
+            PackError::RightArgumentIsMissingForTemplate => "Arguments count is less then template size",
+            PackError::InvalidFormatLengthArgument => "Len for the argument is invalid",
+            PackError::EmptyFormatCharacter => "Format character is empty",
+            PackError::InvalidFormatCharacter => "Format character is not supported",
+            PackError::InvalidFormatModifier => "Format modifier is not supported by the format character",
+            PackError::EmptyTemplate => "Template is empty",
+            PackError::NonFiniteInteger => "Cannot pack Inf or NaN with an integer format",
+            PackError::UnbalancedParentheses => "Group parentheses are not balanced",
+            PackError::InvalidLengthItem => "Format cannot hold a length before `/` or take one after it",
+            PackError::PositionOutsideOfString => "Position is outside of the packed string",
+            PackError::InvalidChecksum => "Checksum `%` only goes before a numeric format, a character or a bit string, in unpack",
+            PackError::NegativeCompressedInteger => "Cannot compress a negative number with `w` or `e`",
+            PackError::InvalidCharacter => "Value is not a Unicode code point",
+            PackError::WideCharacter => "Characters above 255 need a UTF-8 string, from a template starting with `U` or holding `U0`",
+            PackError::UnsupportedNativeSize => "Native formats can only be 1, 2, 4 or 8 bytes long",
+            PackError::BufferTooSmall => "Buffer is too small for the packed string",
+            PackError::OutOfMemory => "Packed string is too large to be allocated",
+            PackError::ValueOutOfRange => "Value does not fit into the format",
+            PackError::StringTooLong => "String is longer than the format holds",
+            PackError::WrongArgumentType => "Argument can not be packed with the format",
+        })
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "at offset {}, in item {} at {}..{} of the template", self.offset, self.item, self.span.start, self.span.end)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} at offset {}: `{}`", self.error, self.offset, self.snippet)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Error for TemplateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl Error for PackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "alloc")]
+            PackError::Item { cause, .. } => Some(cause.as_ref()),
+            #[cfg(feature = "alloc")]
+            PackError::Template(e) => Some(e.as_ref()),
+            #[cfg(feature = "alloc")]
+            PackError::Other(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
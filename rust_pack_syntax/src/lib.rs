@@ -0,0 +1,949 @@
+//! The grammar of `rust_pack` templates: [`PackType`]s, the [`Reader`] of the items of a template
+//! and the parser building their tree out of it, which keeps where every item is in the source,
+//! and the errors of templates and of packing.
+//!
+//! `rust_pack` parses templates with it at runtime and `rust_pack_macros` while compiling, so both read them the same way.
+//! This crate is an implementation detail, use the types re-exported by `rust_pack` instead.
+//! Without the default `alloc` feature only the [`Reader`] is left: [`PackType`] has no groups, `/` nor `%`,
+//! which it still reads, and there is no `parse`.
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::string::{String, ToString};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::ops::Range;
+
+mod error;
+
+pub use error::{Location, PackError};
+#[cfg(feature = "alloc")]
+pub use error::TemplateError;
+
+/// https://perldoc.perl.org/functions/pack
+#[derive(Debug, Clone, PartialEq)]
+pub enum PackType {
+    /// A string with arbitrary binary data, will be null padded.
+    StringNullPadded(Count),
+    /// A text (ASCII) string, will be space padded.
+    AsciiNullPadded(Count),
+    /// A null-terminated (ASCIZ) string, will be null padded.
+    AscizNullPadded(Count),
+    /// A bit string (ascending bit order inside each byte, like vec()).
+    BitStringAscending(Count),
+    /// A bit string (descending bit order inside each byte).
+    BitStringDescending(Count),
+    /// A hex string (low nybble first).
+    HexStringLowFirst(Count),
+    /// A hex string (high nybble first).
+    HexStringHighFirst(Count),
+    /// A uuencoded string, the count is the number of bytes per line: 45 without one (or with 1, 2 or `*`),
+    /// otherwise rounded down to a multiple of 3, 63 at most.
+    Uuencoded(Count),
+    /// A signed char (8-bit) value.
+    SignedChar(Count),
+    /// An unsigned char (octet) value.
+    UnsignedChar(Count),
+    /// An unsigned char value that can be greater than 255: a character in UTF-8 strings, a byte otherwise.
+    WideChar(Count),
+    /// A Unicode code point, always stored UTF-8 encoded.
+    UnicodeChar(Count),
+    /// `C0`: `a`, `A`, `Z`, `c` and `C` handle characters of a UTF-8 string instead of its bytes,
+    /// the default unless the template starts with `U`. Has no effect in other strings.
+    CharacterMode,
+    /// `U0`: `a`, `A`, `Z`, `c` and `C` handle the bytes of a UTF-8 string instead of its characters,
+    /// the default when the template starts with `U`.
+    /// The string is UTF-8 when the template starts with `U` or holds `U0`.
+    ByteMode,
+    /// A signed short (16-bit) value.
+    SignedShort(Count, Endianness),
+    /// An unsigned short value.
+    UnsignedShort(Count, Endianness),
+    /// A signed long (32-bit) value.
+    SignedLong(Count, Endianness),
+    /// An unsigned long value.
+    UnsignedLong(Count, Endianness),
+    /// A signed quad (64-bit) value.
+    SignedQuad(Count, Endianness),
+    /// An unsigned quad value.
+    UnsignedQuad(Count, Endianness),
+    /// A signed short of the platform's C `short` size, `s!`.
+    NativeSignedShort(Count, Endianness),
+    /// An unsigned short of the platform's C `short` size, `S!`.
+    NativeUnsignedShort(Count, Endianness),
+    /// A signed integer of the platform's C `int` size, `i` or `i!`.
+    SignedInteger(Count, Endianness),
+    /// An unsigned integer of the platform's C `int` size, `I` or `I!`.
+    UnsignedInteger(Count, Endianness),
+    /// A signed long of the platform's C `long` size, `l!`.
+    NativeSignedLong(Count, Endianness),
+    /// An unsigned long of the platform's C `long` size, `L!`.
+    NativeUnsignedLong(Count, Endianness),
+    /// A Perl internal signed integer (IV), pointer sized, `j`.
+    PerlSignedInteger(Count, Endianness),
+    /// A Perl internal unsigned integer (UV), pointer sized, `J`.
+    PerlUnsignedInteger(Count, Endianness),
+    /// An unsigned short (16-bit) in "network" (big-endian) order.
+    UnsignedShortBE(Count),
+    /// An unsigned long (32-bit) in "network" (big-endian) order.
+    UnsignedLongBE(Count),
+    /// An unsigned short (16-bit) in "VAX" (little-endian) order.
+    UnsignedShortLE(Count),
+    /// An unsigned long (32-bit) in "VAX" (little-endian) order.
+    UnsignedLongLE(Count),
+    /// A signed short (16-bit) in "network" (big-endian) order, `n!`.
+    SignedShortBE(Count),
+    /// A signed long (32-bit) in "network" (big-endian) order, `N!`.
+    SignedLongBE(Count),
+    /// A signed short (16-bit) in "VAX" (little-endian) order, `v!`.
+    SignedShortLE(Count),
+    /// A signed long (32-bit) in "VAX" (little-endian) order, `V!`.
+    SignedLongLE(Count),
+    /// A single-precision float in native format.
+    Float(Count, Endianness),
+    /// A double-precision float in native format.
+    Double(Count, Endianness),
+    /// A float of Perl's internal floating-point type (NV), which is a double.
+    PerlFloat(Count, Endianness),
+    /// A long double, stored as a 128-bit IEEE quadruple precision approximation of a double.
+    LongDouble(Count, Endianness),
+    /// An unsigned integer in BER compressed form: base 128 digits, most significant first,
+    /// with the high bit set on every byte but the last.
+    BerCompressed(Count),
+    /// An unsigned LEB128 integer (a protobuf varint): base 128 digits, least significant first,
+    /// with the high bit set on every byte but the last. Not in Perl, `e`.
+    UnsignedLeb128(Count),
+    /// A signed LEB128 integer, sign extended from the last byte. Not in Perl, `E`.
+    SignedLeb128(Count),
+    /// A signed integer zigzag encoded as an unsigned LEB128 (a protobuf `sint` varint). Not in Perl, `z`.
+    ZigZagVarint(Count),
+    /// A null byte (a.k.a ASCII NUL, "\000", chr(0))
+    NullByte(Count),
+    /// Null bytes up to a multiple of the count, `x!`.
+    NullByteAlign(Count),
+    /// Back up a byte.
+    BackUpByte(Count),
+    /// Back up to a multiple of the count, `X!`.
+    BackUpByteAlign(Count),
+    /// Null-fill or truncate to an absolute position, counted from the start of the innermost group, `@`.
+    AbsolutePosition(Count),
+    /// Null-fill or truncate to the position given by the argument, `.`; on unpack, the current position.
+    /// The position is counted from the start of the innermost group, from the `count`th enclosing group,
+    /// from the current position with `.0` and from the start of the string with `.*`.
+    ValuePosition(Count),
+    /// A parenthesized group of formats, repeated `count` times as a whole.
+    #[cfg(feature = "alloc")]
+    Group(Vec<PackType>, Count),
+    /// `length-item/sequence-item`: the length of a string, or the repeat count of a numeric format or a group,
+    /// packed with the first format before the second one.
+    #[cfg(feature = "alloc")]
+    LengthPrefixed(Box<PackType>, Box<PackType>),
+    /// `%<bits>` before a numeric format, `W`, `U` or a bit string, unpack only: the sum of the values modulo 2^bits
+    /// (or of the set bits of a bit string) instead of the values.
+    #[cfg(feature = "alloc")]
+    Checksum(u32, Box<PackType>),
+}
+
+/// The count (or length) argument that follows the format character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Count {
+    /// An explicit number, `1` when the template has none.
+    Exact(usize),
+    /// `*`: the whole argument for string formats, all the remaining arguments for numeric formats,
+    /// and all the remaining data on unpack.
+    Star,
+}
+
+impl Count {
+    /// The exact count, or `star` for `*`.
+    pub fn or(self, star: usize) -> usize {
+        match self {
+            Count::Exact(c) => c,
+            Count::Star => star,
+        }
+    }
+}
+
+impl Default for Count {
+    fn default() -> Self {
+        Count::Exact(1)
+    }
+}
+
+/// Byte order of a multi-byte value, chosen with the `<` (little-endian) and `>` (big-endian) modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endianness {
+    #[default]
+    Native,
+    Little,
+    Big,
+}
+
+/// Sizes in bytes of the C types behind the native formats, [`Abi::NATIVE`] unless
+/// `pack_with_abi` or `unpack_with_abi` handle data of another platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Abi {
+    /// `short`, for `s!` and `S!`.
+    pub short: usize,
+    /// `int`, for `i` and `I`.
+    pub int: usize,
+    /// `long`, for `l!` and `L!`.
+    pub long: usize,
+    /// Perl's `IV` and `UV`, for `j` and `J`.
+    pub iv: usize,
+}
+
+impl Abi {
+    /// The platform this is compiled for.
+    pub const NATIVE: Abi = Abi {
+        short: size_of::<core::ffi::c_short>(),
+        int: size_of::<core::ffi::c_int>(),
+        long: size_of::<core::ffi::c_long>(),
+        iv: size_of::<isize>(),
+    };
+    /// 64-bit Unix: 64-bit longs and pointers.
+    pub const LP64: Abi = Abi { short: 2, int: 4, long: 8, iv: 8 };
+    /// 64-bit Windows: 32-bit longs and 64-bit pointers.
+    pub const LLP64: Abi = Abi { short: 2, int: 4, long: 4, iv: 8 };
+    /// 32-bit platforms: 32-bit ints, longs and pointers.
+    pub const ILP32: Abi = Abi { short: 2, int: 4, long: 4, iv: 4 };
+
+    /// Size of a single value of a native format, `None` for the other formats.
+    pub(crate) fn size_of(&self, pack_type: &PackType) -> Option<usize> {
+        match pack_type {
+            PackType::NativeSignedShort(..) | PackType::NativeUnsignedShort(..) => Some(self.short),
+            PackType::SignedInteger(..) | PackType::UnsignedInteger(..) => Some(self.int),
+            PackType::NativeSignedLong(..) | PackType::NativeUnsignedLong(..) => Some(self.long),
+            PackType::PerlSignedInteger(..) | PackType::PerlUnsignedInteger(..) => Some(self.iv),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&str> for PackType {
+    type Error = PackError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut chars = value.chars();
+        let letter = match chars.next() {
+            Some(letter) => letter,
+            None => return Err(PackError::EmptyFormatCharacter),
+        };
+        let (size, endianness, bang) = parse_modifiers_and_count(chars.as_str())?;
+        PackType::from_parts(letter, size, endianness, bang)
+    }
+}
+
+impl PackType {
+    /// The format of a format character with its count and modifiers.
+    pub(crate) fn from_parts(letter: char, size: Count, endianness: Endianness, bang: bool) -> Result<PackType, PackError> {
+        let (little, big) = (endianness == Endianness::Little, endianness == Endianness::Big);
+        match (letter, bang) {
+            ('s', false) => return Ok(Self::SignedShort(size, endianness)),
+            ('S', false) => return Ok(Self::UnsignedShort(size, endianness)),
+            ('l', false) => return Ok(Self::SignedLong(size, endianness)),
+            ('L', false) => return Ok(Self::UnsignedLong(size, endianness)),
+            ('s', true) => return Ok(Self::NativeSignedShort(size, endianness)),
+            ('S', true) => return Ok(Self::NativeUnsignedShort(size, endianness)),
+            ('l', true) => return Ok(Self::NativeSignedLong(size, endianness)),
+            ('L', true) => return Ok(Self::NativeUnsignedLong(size, endianness)),
+            ('i', _) => return Ok(Self::SignedInteger(size, endianness)),
+            ('I', _) => return Ok(Self::UnsignedInteger(size, endianness)),
+            ('j', false) => return Ok(Self::PerlSignedInteger(size, endianness)),
+            ('J', false) => return Ok(Self::PerlUnsignedInteger(size, endianness)),
+            ('q', false) => return Ok(Self::SignedQuad(size, endianness)),
+            ('Q', false) => return Ok(Self::UnsignedQuad(size, endianness)),
+            ('f', false) => return Ok(Self::Float(size, endianness)),
+            ('d', false) => return Ok(Self::Double(size, endianness)),
+            ('F', false) => return Ok(Self::PerlFloat(size, endianness)),
+            ('D', false) => return Ok(Self::LongDouble(size, endianness)),
+            _ if little || big => return Err(PackError::InvalidFormatModifier),
+            ('n', true) => return Ok(Self::SignedShortBE(size)),
+            ('N', true) => return Ok(Self::SignedLongBE(size)),
+            ('v', true) => return Ok(Self::SignedShortLE(size)),
+            ('V', true) => return Ok(Self::SignedLongLE(size)),
+            ('x', true) => return Ok(Self::NullByteAlign(size)),
+            ('X', true) => return Ok(Self::BackUpByteAlign(size)),
+            (_, true) => return Err(PackError::InvalidFormatModifier),
+            _ => {}
+        }
+        // https://perldoc.perl.org/functions/pack
+        match letter {
+            'a' => Ok(Self::StringNullPadded(size)),
+            'A' => Ok(Self::AsciiNullPadded(size)),
+            'Z' => Ok(Self::AscizNullPadded(size)),
+            'b' => Ok(Self::BitStringAscending(size)),
+            'B' => Ok(Self::BitStringDescending(size)),
+            'h' => Ok(Self::HexStringLowFirst(size)),
+            'H' => Ok(Self::HexStringHighFirst(size)),
+            'u' => Ok(Self::Uuencoded(size)),
+            'c' => Ok(Self::SignedChar(size)),
+            'C' if size == Count::Exact(0) => Ok(Self::CharacterMode),
+            'C' => Ok(Self::UnsignedChar(size)),
+            'W' => Ok(Self::WideChar(size)),
+            'U' if size == Count::Exact(0) => Ok(Self::ByteMode),
+            'U' => Ok(Self::UnicodeChar(size)),
+            'n' => Ok(Self::UnsignedShortBE(size)),
+            'N' => Ok(Self::UnsignedLongBE(size)),
+            'v' => Ok(Self::UnsignedShortLE(size)),
+            'V' => Ok(Self::UnsignedLongLE(size)),
+            'w' => Ok(Self::BerCompressed(size)),
+            'e' => Ok(Self::UnsignedLeb128(size)),
+            'E' => Ok(Self::SignedLeb128(size)),
+            'z' => Ok(Self::ZigZagVarint(size)),
+            'x' => Ok(Self::NullByte(size)),
+            'X' => Ok(Self::BackUpByte(size)),
+            '@' => Ok(Self::AbsolutePosition(size)),
+            '.' => Ok(Self::ValuePosition(size)),
+            _ => Err(PackError::InvalidFormatCharacter),
+        }
+    }
+}
+
+/// Splits what follows a format character (or a group) into its count, its byte order and whether `!` is present.
+pub(crate) fn parse_modifiers_and_count(rest: &str) -> Result<(Count, Endianness, bool), PackError> {
+    let (modifiers, count) = rest.split_at(rest.find(|c| !matches!(c, '<' | '>' | '!')).unwrap_or(rest.len()));
+    let size = match count {
+        "" => Count::default(),
+        "*" => Count::Star,
+        _ => {
+            let count = count.strip_prefix('[').and_then(|c| c.strip_suffix(']')).unwrap_or(count);
+            match count.parse::<usize>() {
+                Ok(s) => Count::Exact(s),
+                Err(_) => return Err(PackError::InvalidFormatLengthArgument),
+            }
+        }
+    };
+    let endianness = match (modifiers.contains('<'), modifiers.contains('>')) {
+        (false, false) => Endianness::Native,
+        (true, false) => Endianness::Little,
+        (false, true) => Endianness::Big,
+        (true, true) => return Err(PackError::InvalidFormatModifier),
+    };
+    Ok((size, endianness, modifiers.contains('!')))
+}
+
+#[cfg(feature = "alloc")]
+impl TryFrom<String> for PackType {
+    type Error = PackError;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        PackType::try_from(value.as_str())
+    }
+}
+
+impl PackType {
+    /// The count (or length) argument that follows the format character.
+    pub fn count(&self) -> Count {
+        match self {
+            PackType::StringNullPadded(c)
+            | PackType::AsciiNullPadded(c)
+            | PackType::AscizNullPadded(c)
+            | PackType::BitStringAscending(c)
+            | PackType::BitStringDescending(c)
+            | PackType::HexStringLowFirst(c)
+            | PackType::HexStringHighFirst(c)
+            | PackType::Uuencoded(c)
+            | PackType::SignedChar(c)
+            | PackType::UnsignedChar(c)
+            | PackType::WideChar(c)
+            | PackType::UnicodeChar(c)
+            | PackType::SignedShort(c, _)
+            | PackType::UnsignedShort(c, _)
+            | PackType::SignedLong(c, _)
+            | PackType::UnsignedLong(c, _)
+            | PackType::SignedQuad(c, _)
+            | PackType::UnsignedQuad(c, _)
+            | PackType::NativeSignedShort(c, _)
+            | PackType::NativeUnsignedShort(c, _)
+            | PackType::SignedInteger(c, _)
+            | PackType::UnsignedInteger(c, _)
+            | PackType::NativeSignedLong(c, _)
+            | PackType::NativeUnsignedLong(c, _)
+            | PackType::PerlSignedInteger(c, _)
+            | PackType::PerlUnsignedInteger(c, _)
+            | PackType::UnsignedShortBE(c)
+            | PackType::UnsignedLongBE(c)
+            | PackType::UnsignedShortLE(c)
+            | PackType::UnsignedLongLE(c)
+            | PackType::SignedShortBE(c)
+            | PackType::SignedLongBE(c)
+            | PackType::SignedShortLE(c)
+            | PackType::SignedLongLE(c)
+            | PackType::BerCompressed(c)
+            | PackType::UnsignedLeb128(c)
+            | PackType::SignedLeb128(c)
+            | PackType::ZigZagVarint(c)
+            | PackType::NullByte(c)
+            | PackType::NullByteAlign(c)
+            | PackType::BackUpByte(c)
+            | PackType::BackUpByteAlign(c)
+            | PackType::AbsolutePosition(c)
+            | PackType::ValuePosition(c)
+            | PackType::Float(c, _)
+            | PackType::Double(c, _)
+            | PackType::PerlFloat(c, _)
+            | PackType::LongDouble(c, _) => *c,
+            #[cfg(feature = "alloc")]
+            PackType::Group(_, c) => *c,
+            #[cfg(feature = "alloc")]
+            PackType::LengthPrefixed(_, item) | PackType::Checksum(_, item) => item.count(),
+            PackType::CharacterMode | PackType::ByteMode => Count::Exact(0),
+        }
+    }
+
+    /// The count to change, `None` for the `C0` and `U0` mode switches.
+    pub(crate) fn count_mut(&mut self) -> Option<&mut Count> {
+        Some(match self {
+            PackType::StringNullPadded(c)
+            | PackType::AsciiNullPadded(c)
+            | PackType::AscizNullPadded(c)
+            | PackType::BitStringAscending(c)
+            | PackType::BitStringDescending(c)
+            | PackType::HexStringLowFirst(c)
+            | PackType::HexStringHighFirst(c)
+            | PackType::Uuencoded(c)
+            | PackType::SignedChar(c)
+            | PackType::UnsignedChar(c)
+            | PackType::WideChar(c)
+            | PackType::UnicodeChar(c)
+            | PackType::SignedShort(c, _)
+            | PackType::UnsignedShort(c, _)
+            | PackType::SignedLong(c, _)
+            | PackType::UnsignedLong(c, _)
+            | PackType::SignedQuad(c, _)
+            | PackType::UnsignedQuad(c, _)
+            | PackType::NativeSignedShort(c, _)
+            | PackType::NativeUnsignedShort(c, _)
+            | PackType::SignedInteger(c, _)
+            | PackType::UnsignedInteger(c, _)
+            | PackType::NativeSignedLong(c, _)
+            | PackType::NativeUnsignedLong(c, _)
+            | PackType::PerlSignedInteger(c, _)
+            | PackType::PerlUnsignedInteger(c, _)
+            | PackType::UnsignedShortBE(c)
+            | PackType::UnsignedLongBE(c)
+            | PackType::UnsignedShortLE(c)
+            | PackType::UnsignedLongLE(c)
+            | PackType::SignedShortBE(c)
+            | PackType::SignedLongBE(c)
+            | PackType::SignedShortLE(c)
+            | PackType::SignedLongLE(c)
+            | PackType::BerCompressed(c)
+            | PackType::UnsignedLeb128(c)
+            | PackType::SignedLeb128(c)
+            | PackType::ZigZagVarint(c)
+            | PackType::NullByte(c)
+            | PackType::NullByteAlign(c)
+            | PackType::BackUpByte(c)
+            | PackType::BackUpByteAlign(c)
+            | PackType::AbsolutePosition(c)
+            | PackType::ValuePosition(c)
+            | PackType::Float(c, _)
+            | PackType::Double(c, _)
+            | PackType::PerlFloat(c, _)
+            | PackType::LongDouble(c, _) => c,
+            #[cfg(feature = "alloc")]
+            PackType::Group(_, c) => c,
+            #[cfg(feature = "alloc")]
+            PackType::LengthPrefixed(_, item) | PackType::Checksum(_, item) => return item.count_mut(),
+            PackType::CharacterMode | PackType::ByteMode => return None,
+        })
+    }
+
+    /// The same format with another count.
+    pub fn with_count(&self, count: Count) -> PackType {
+        let mut pack_type = self.clone();
+        if let Some(c) = pack_type.count_mut() {
+            *c = count;
+        }
+        pack_type
+    }
+
+    /// The byte order to change, `None` for the formats without one.
+    pub(crate) fn endianness_mut(&mut self) -> Option<&mut Endianness> {
+        match self {
+            PackType::SignedShort(_, e)
+            | PackType::UnsignedShort(_, e)
+            | PackType::SignedLong(_, e)
+            | PackType::UnsignedLong(_, e)
+            | PackType::SignedQuad(_, e)
+            | PackType::UnsignedQuad(_, e)
+            | PackType::NativeSignedShort(_, e)
+            | PackType::NativeUnsignedShort(_, e)
+            | PackType::SignedInteger(_, e)
+            | PackType::UnsignedInteger(_, e)
+            | PackType::NativeSignedLong(_, e)
+            | PackType::NativeUnsignedLong(_, e)
+            | PackType::PerlSignedInteger(_, e)
+            | PackType::PerlUnsignedInteger(_, e)
+            | PackType::Float(_, e)
+            | PackType::Double(_, e)
+            | PackType::PerlFloat(_, e)
+            | PackType::LongDouble(_, e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Whether the count is a length (of a string, a bit string or a hex string) instead of a repeat count.
+    pub fn is_string(&self) -> bool {
+        matches!(self, PackType::StringNullPadded(_)
+            | PackType::AsciiNullPadded(_)
+            | PackType::AscizNullPadded(_)
+            | PackType::BitStringAscending(_)
+            | PackType::BitStringDescending(_)
+            | PackType::HexStringLowFirst(_)
+            | PackType::HexStringHighFirst(_))
+    }
+
+    /// Whether the format moves the position in the string instead of packing a value (`x` aside).
+    pub fn is_position(&self) -> bool {
+        matches!(self, PackType::NullByteAlign(_)
+            | PackType::BackUpByte(_)
+            | PackType::BackUpByteAlign(_)
+            | PackType::AbsolutePosition(_)
+            | PackType::ValuePosition(_))
+    }
+
+    /// Whether the format's size is the one of a C type of the platform: `s!`, `S!`, `i`, `I`, `l!`, `L!`, `j` or `J`.
+    pub(crate) fn is_native(&self) -> bool {
+        Abi::NATIVE.size_of(self).is_some()
+    }
+
+    /// Whether the format is a variable length integer: `w`, `e`, `E` or `z`.
+    pub fn is_varint(&self) -> bool {
+        matches!(self, PackType::BerCompressed(_)
+            | PackType::UnsignedLeb128(_)
+            | PackType::SignedLeb128(_)
+            | PackType::ZigZagVarint(_))
+    }
+
+    /// Size in bytes of a single value of a numeric format, `None` for other formats and for varints.
+    pub fn size(&self) -> Option<usize> {
+        match self {
+            PackType::Float(..) => Some(4),
+            PackType::Double(..) | PackType::PerlFloat(..) => Some(8),
+            PackType::LongDouble(..) => Some(16),
+            _ => self.integer_layout().map(|(size, _, _)| size),
+        }
+    }
+
+    /// Size, signedness and byte order of an integer format.
+    #[doc(hidden)]
+    pub fn integer_layout(&self) -> Option<(usize, bool, Endianness)> {
+        match self {
+            PackType::SignedChar(_) => Some((1, true, Endianness::Native)),
+            PackType::UnsignedChar(_) => Some((1, false, Endianness::Native)),
+            PackType::SignedShort(_, e) => Some((2, true, *e)),
+            PackType::UnsignedShort(_, e) => Some((2, false, *e)),
+            PackType::SignedLong(_, e) => Some((4, true, *e)),
+            PackType::UnsignedLong(_, e) => Some((4, false, *e)),
+            PackType::SignedQuad(_, e) => Some((8, true, *e)),
+            PackType::UnsignedQuad(_, e) => Some((8, false, *e)),
+            PackType::NativeSignedShort(_, e) => Some((Abi::NATIVE.short, true, *e)),
+            PackType::NativeUnsignedShort(_, e) => Some((Abi::NATIVE.short, false, *e)),
+            PackType::SignedInteger(_, e) => Some((Abi::NATIVE.int, true, *e)),
+            PackType::UnsignedInteger(_, e) => Some((Abi::NATIVE.int, false, *e)),
+            PackType::NativeSignedLong(_, e) => Some((Abi::NATIVE.long, true, *e)),
+            PackType::NativeUnsignedLong(_, e) => Some((Abi::NATIVE.long, false, *e)),
+            PackType::PerlSignedInteger(_, e) => Some((Abi::NATIVE.iv, true, *e)),
+            PackType::PerlUnsignedInteger(_, e) => Some((Abi::NATIVE.iv, false, *e)),
+            PackType::UnsignedShortBE(_) => Some((2, false, Endianness::Big)),
+            PackType::UnsignedLongBE(_) => Some((4, false, Endianness::Big)),
+            PackType::UnsignedShortLE(_) => Some((2, false, Endianness::Little)),
+            PackType::UnsignedLongLE(_) => Some((4, false, Endianness::Little)),
+            PackType::SignedShortBE(_) => Some((2, true, Endianness::Big)),
+            PackType::SignedLongBE(_) => Some((4, true, Endianness::Big)),
+            PackType::SignedShortLE(_) => Some((2, true, Endianness::Little)),
+            PackType::SignedLongLE(_) => Some((4, true, Endianness::Little)),
+            _ => None,
+        }
+    }
+}
+
+/// Parses `source` into its items and where each of them is, see `Template::spans`.
+/// `[template]` counts holding native formats take their sizes from `abi`, and are an error without one:
+/// the macros can't know the sizes, the target may not be the platform compiling.
+#[cfg(feature = "alloc")]
+pub fn parse(source: &str, abi: Option<Abi>) -> Result<(Vec<PackType>, Vec<Range<usize>>), TemplateError> {
+    let mut spans = Vec::new();
+    let items = collect(read(source, abi), &mut spans).map_err(|(error, span)| template_error(source, error, span))?;
+    if items.is_empty() {
+        return Err(template_error(source, PackError::EmptyTemplate, 0..0));
+    }
+    Ok((items, spans))
+}
+
+/// The result of reading templates, with an error where in the source it is.
+type Located<T> = Result<T, (PackError, Range<usize>)>;
+
+/// Builds the tree of the items of `reader`, pushing their spans in the order they are met.
+#[cfg(feature = "alloc")]
+fn collect(reader: Reader<'_>, spans: &mut Vec<Range<usize>>) -> Located<Vec<PackType>> {
+    let mut items = Vec::new();
+    for item in reader {
+        let item = item?;
+        spans.push(item.span);
+        items.push(match item.token {
+            Token::Format(pack_type) => pack_type,
+            Token::Group(group, count) => PackType::Group(collect(group, spans)?, count),
+            Token::LengthPrefixed(length, sequence) => {
+                let length = collect(length, spans)?.remove(0);
+                PackType::LengthPrefixed(Box::new(length), Box::new(collect(sequence, spans)?.remove(0)))
+            }
+            Token::Checksum(bits, item) => PackType::Checksum(bits, Box::new(collect(item, spans)?.remove(0))),
+        });
+    }
+    Ok(items)
+}
+
+#[cfg(feature = "alloc")]
+fn template_error(source: &str, error: PackError, span: Range<usize>) -> TemplateError {
+    // a single offending character may not be ASCII
+    let end = match source[span.start..].chars().next() {
+        Some(c) if span.len() == 1 => span.start + c.len_utf8(),
+        _ => span.end,
+    };
+    TemplateError { error, offset: span.start, snippet: source[span.start..end].to_string() }
+}
+
+/// Reads the items of `source` one at a time, without allocating, see [`Reader`].
+pub fn read(source: &str, abi: Option<Abi>) -> Reader<'_> {
+    Reader { source, position: 0, close: None, endianness: Endianness::Native, single: false, star: false, index: 0, abi }
+}
+
+/// The items of a template read one at a time and checked as [`parse`] does, but without building their tree:
+/// groups, `/` and `%` come with readers of the items they hold, to be read as many times as needed.
+/// Errors come with the byte range of the template they are about.
+#[derive(Debug, Clone)]
+pub struct Reader<'s> {
+    source: &'s str,
+    position: usize,
+    /// The `)` or `]` ending the items of a group or of a `[template]` count, `None` for the whole template.
+    close: Option<u8>,
+    /// Byte order given by the groups around the items.
+    endianness: Endianness,
+    /// Reads a single item: the length or the item of a `/`, the format of a `%`.
+    single: bool,
+    /// The single item follows a `/` without a count, so its count is `*`.
+    star: bool,
+    /// Index of the next item in the spans.
+    index: usize,
+    /// Sizes of the native formats in `[template]` counts.
+    abi: Option<Abi>,
+}
+
+/// An item read by a [`Reader`], with where it is.
+#[derive(Debug, Clone)]
+pub struct Item<'s> {
+    pub token: Token<'s>,
+    /// Index of the item in `Template::spans`.
+    pub index: usize,
+    /// Byte range of the item in the template.
+    pub span: Range<usize>,
+}
+
+/// What a [`Reader`] reads: a format, or what holds other items.
+#[derive(Debug, Clone)]
+pub enum Token<'s> {
+    /// A format, which is never a group, a `/` or a `%`.
+    Format(PackType),
+    /// The items of a parenthesized group, and how many times they are repeated.
+    Group(Reader<'s>, Count),
+    /// The length of a `/` and the item following it.
+    LengthPrefixed(Reader<'s>, Reader<'s>),
+    /// The number of bits of a `%` and its format.
+    Checksum(u32, Reader<'s>),
+}
+
+/// What the items read hold, for the groups around them to check.
+#[derive(Default)]
+struct Summary {
+    /// Number of items in the trees of the items, as they are counted in the spans.
+    nodes: usize,
+    /// Whether some format has a byte order, and whether it is little-endian or big-endian.
+    ordered: bool,
+    little: bool,
+    big: bool,
+}
+
+impl Summary {
+    fn add(&mut self, other: Summary) {
+        self.nodes += other.nodes;
+        self.ordered |= other.ordered;
+        self.little |= other.little;
+        self.big |= other.big;
+    }
+}
+
+impl<'s> Iterator for Reader<'s> {
+    type Item = Result<Item<'s>, (PackError, Range<usize>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_item().map(|item| item.map(|(item, _)| item))
+    }
+}
+
+impl<'s> Reader<'s> {
+    fn peek(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.position).copied()
+    }
+
+    /// A reader of the single item at `position`.
+    fn single(&self, position: usize, index: usize) -> Reader<'s> {
+        Reader { position, single: true, star: false, index, ..self.clone() }
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_blanks(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                b'#' => {
+                    let rest = &self.source[self.position..];
+                    self.position += rest.find('\n').unwrap_or(rest.len());
+                }
+                c if c.is_ascii_whitespace() => self.position += 1,
+                _ => return,
+            }
+        }
+    }
+
+    /// Reads the next item, and a `/` following it, until the end of the template or until `close`, which is left unread.
+    fn read_item(&mut self) -> Option<Located<(Item<'s>, Summary)>> {
+        self.skip_blanks();
+        if self.peek().is_none() || self.peek() == self.close {
+            return None;
+        }
+        let (index, start) = (self.index, self.position);
+        let item = self.read_one(index).and_then(|(token, span, summary)| match self.single {
+            true => {
+                // the reader of a single item ends with it
+                self.position = self.source.len();
+                let token = match token {
+                    Token::Format(p) if self.star => Token::Format(p.with_count(Count::Star)),
+                    Token::Group(items, _) if self.star => Token::Group(items, Count::Star),
+                    token => token,
+                };
+                Ok((Item { token, index, span }, summary))
+            }
+            false => self.read_length_prefixed(token, start, span, summary),
+        });
+        if let Ok((_, summary)) = &item {
+            self.index += summary.nodes;
+        }
+        Some(item)
+    }
+
+    /// Reads the `/` and the item following `length`, if any.
+    fn read_length_prefixed(&mut self, length: Token<'s>, start: usize, span: Range<usize>, summary: Summary) -> Located<(Item<'s>, Summary)> {
+        let index = self.index;
+        self.skip_blanks();
+        if self.peek() != Some(b'/') {
+            return Ok((Item { token: length, index, span }, summary));
+        }
+        let length_holds_count = match &length {
+            Token::Group(..) | Token::LengthPrefixed(..) | Token::Checksum(..) => false,
+            Token::Format(PackType::NullByte(_) | PackType::CharacterMode | PackType::ByteMode | PackType::Uuencoded(_)) => false,
+            Token::Format(p) if p.is_position() => false,
+            Token::Format(p) if p.is_string() => p.count() != Count::Star,
+            Token::Format(p) => p.count() == Count::Exact(1),
+        };
+        if !length_holds_count {
+            return Err((PackError::InvalidLengthItem, start..span.end));
+        }
+        let slash = self.position;
+        self.position += 1;
+        self.skip_blanks();
+        if self.peek().is_none() || self.peek() == self.close {
+            return Err((PackError::EmptyFormatCharacter, slash..slash + 1)); // nothing follows the `/`
+        }
+        let sequence_start = self.position;
+        let (sequence, sequence_span, mut sequence_summary) = self.read_one(index + 1 + summary.nodes)?;
+        let invalid = match &sequence {
+            Token::Format(p) => p.is_position() || matches!(p, PackType::CharacterMode | PackType::ByteMode | PackType::Uuencoded(_)),
+            Token::Checksum(..) => true,
+            _ => false,
+        };
+        if invalid {
+            return Err((PackError::InvalidLengthItem, sequence_span));
+        }
+        let mut sequence = self.single(sequence_start, index + 1 + summary.nodes);
+        // without a count the whole string, or all the remaining arguments, are packed
+        sequence.star = !matches!(self.source.as_bytes()[self.position - 1], b'*' | b']' | b'0'..=b'9');
+        let token = Token::LengthPrefixed(self.single(start, index + 1), sequence);
+        sequence_summary.add(summary);
+        sequence_summary.nodes += 1;
+        Ok((Item { token, index, span: start..self.position }, sequence_summary))
+    }
+
+    /// Reads a single format, group or checksum, with its modifiers and count, `index` being its index in the spans.
+    fn read_one(&mut self, index: usize) -> Located<(Token<'s>, Range<usize>, Summary)> {
+        let start = self.position;
+        let (token, summary) = match self.peek().unwrap() {
+            b'%' => {
+                self.position += 1;
+                let digits = self.source[self.position..].bytes().take_while(u8::is_ascii_digit).count();
+                let bits = match &self.source[self.position..self.position + digits] {
+                    "" => 16,
+                    bits => bits.parse::<u32>().map_err(|_| (PackError::InvalidFormatLengthArgument, start..self.position + digits))?,
+                };
+                self.position += digits;
+                if !self.peek().is_some_and(|c| c.is_ascii_alphabetic() || c == b'(') {
+                    return Err((PackError::EmptyFormatCharacter, start..self.position)); // nothing follows the `%`
+                }
+                let item = self.single(self.position, index + 1);
+                let (token, _, mut summary) = self.read_one(index + 1)?;
+                let summable = match &token {
+                    Token::Format(p) => p.size().is_some() || p.is_varint() || matches!(p, PackType::BitStringAscending(_)
+                        | PackType::BitStringDescending(_) | PackType::WideChar(_) | PackType::UnicodeChar(_)),
+                    _ => false,
+                };
+                if !summable {
+                    return Err((PackError::InvalidChecksum, start..self.position));
+                }
+                summary.nodes += 1;
+                (Token::Checksum(bits, item), summary)
+            }
+            b'(' => {
+                self.position += 1;
+                // the items are checked before the count and the modifiers of the group following them
+                let mut items = Reader { position: self.position, close: Some(b')'), endianness: Endianness::Native, single: false, star: false, index: index + 1, ..*self };
+                let mut summary = Summary::default();
+                while let Some(item) = items.read_item() {
+                    summary.add(item?.1);
+                }
+                self.position = items.position;
+                if self.peek().is_none() {
+                    return Err((PackError::UnbalancedParentheses, start..start + 1));
+                }
+                self.position += 1;
+                let (count, endianness, bang) = self.read_modifiers_and_count(start)?;
+                if bang {
+                    return Err((PackError::InvalidFormatModifier, start..self.position));
+                }
+                // a format with the opposite byte order is an error
+                match endianness {
+                    Endianness::Little if summary.big => return Err((PackError::InvalidFormatModifier, start..self.position)),
+                    Endianness::Big if summary.little => return Err((PackError::InvalidFormatModifier, start..self.position)),
+                    Endianness::Native => {}
+                    e if summary.ordered => (summary.little, summary.big) = (e == Endianness::Little, e == Endianness::Big),
+                    _ => {}
+                }
+                summary.nodes += 1;
+                let items = Reader { position: start + 1, endianness: inherit(self.endianness, endianness), index: index + 1, ..items };
+                (Token::Group(items, count), summary)
+            }
+            c if c.is_ascii_alphabetic() || c == b'@' || c == b'.' => {
+                self.position += 1;
+                let (count, endianness, bang) = self.read_modifiers_and_count(start)?;
+                let mut pack_type = PackType::from_parts(c as char, count, endianness, bang).map_err(|e| (e, start..self.position))?;
+                let mut summary = Summary { nodes: 1, ordered: false, little: endianness == Endianness::Little, big: endianness == Endianness::Big };
+                if let Some(e) = pack_type.endianness_mut() {
+                    *e = inherit(self.endianness, *e);
+                    summary.ordered = true;
+                }
+                (Token::Format(pack_type), summary)
+            }
+            b')' => return Err((PackError::UnbalancedParentheses, start..start + 1)),
+            b'<' | b'>' | b'!' | b'*' | b'[' | b'/' | b'0'..=b'9' => {
+                // a modifier, a count or a `/` without its format
+                return Err((PackError::EmptyFormatCharacter, start..start + 1));
+            }
+            _ => return Err((PackError::InvalidFormatCharacter, start..start + 1)),
+        };
+        Ok((token, start..self.position, summary))
+    }
+
+    /// Reads the modifiers and the count following the format character or the group starting at `start`.
+    fn read_modifiers_and_count(&mut self, start: usize) -> Located<(Count, Endianness, bool)> {
+        let tail = self.position;
+        let rest = &self.source.as_bytes()[tail..];
+        let modifiers = rest.iter().take_while(|c| matches!(c, b'<' | b'>' | b'!')).count();
+        self.position += modifiers;
+        let count = match self.peek() {
+            Some(b'*') => 1,
+            Some(b'0'..=b'9') => self.source[self.position..].bytes().take_while(u8::is_ascii_digit).count(),
+            Some(b'[') => return self.read_bracketed_count(start, tail),
+            _ => 0,
+        };
+        self.position += count;
+        parse_modifiers_and_count(&self.source[tail..self.position]).map_err(|e| (e, start..self.position))
+    }
+
+    /// Reads a `[N]` or `[template]` count, the modifiers before it start at `tail`.
+    fn read_bracketed_count(&mut self, start: usize, tail: usize) -> Located<(Count, Endianness, bool)> {
+        let open = self.position;
+        self.position += 1;
+        let digits = self.source[self.position..].bytes().take_while(u8::is_ascii_digit).count();
+        let count = match self.source.as_bytes().get(self.position + digits) {
+            Some(b']') if digits > 0 => {
+                self.position += digits;
+                self.source[open + 1..self.position].parse::<usize>().ok()
+            }
+            _ => {
+                // the size of a template, whose items have no place among the spans
+                let mut items = Reader { position: self.position, close: Some(b']'), endianness: Endianness::Native, single: false, star: false, index: 0, ..*self };
+                let empty = items.clone().next().is_none();
+                let size = byte_size(&mut items)?.filter(|_| !empty);
+                self.position = items.position;
+                size
+            }
+        };
+        if self.peek() != Some(b']') {
+            return Err((PackError::InvalidFormatLengthArgument, open..self.position));
+        }
+        self.position += 1;
+        let count = count.ok_or((PackError::InvalidFormatLengthArgument, open..self.position))?;
+        let (_, endianness, bang) = parse_modifiers_and_count(&self.source[tail..open]).map_err(|e| (e, start..self.position))?;
+        Ok((Count::Exact(count), endianness, bang))
+    }
+}
+
+/// The byte order of an item in a group of byte order `outer`, which the item agrees with.
+fn inherit(outer: Endianness, endianness: Endianness) -> Endianness {
+    match endianness {
+        Endianness::Native => outer,
+        e => e,
+    }
+}
+
+/// Size in bytes of what the items pack, `None` when it depends on the values, or on the target without an `abi`.
+/// Every item is read, for its errors.
+fn byte_size(items: &mut Reader<'_>) -> Located<Option<usize>> {
+    let abi = items.abi;
+    let mut size = Some(0usize);
+    while let Some(item) = items.read_item() {
+        let item_size = match item?.0.token {
+            Token::Format(PackType::CharacterMode | PackType::ByteMode) => Some(0),
+            Token::Group(mut items, Count::Exact(n)) => byte_size(&mut items)?.and_then(|s| s.checked_mul(n)),
+            Token::Format(p) => match p.count() {
+                Count::Star => None,
+                Count::Exact(n) => match p {
+                    p if p.is_native() => abi.and_then(|abi| abi.size_of(&p)).and_then(|s| s.checked_mul(n)),
+                    PackType::StringNullPadded(_) | PackType::AsciiNullPadded(_) | PackType::AscizNullPadded(_) | PackType::NullByte(_) => Some(n),
+                    PackType::BitStringAscending(_) | PackType::BitStringDescending(_) => Some(n.div_ceil(8)),
+                    PackType::HexStringLowFirst(_) | PackType::HexStringHighFirst(_) => Some(n.div_ceil(2)),
+                    p => p.size().and_then(|s| s.checked_mul(n)),
+                },
+            },
+            Token::Group(..) | Token::LengthPrefixed(..) | Token::Checksum(..) => None,
+        };
+        size = size.zip(item_size).and_then(|(size, item_size)| size.checked_add(item_size));
+    }
+    Ok(size)
+}
+
+/// Number of items in the tree of `pack_type`, itself included, as they are counted in `Template::spans`.
+#[cfg(feature = "alloc")]
+pub fn nodes(pack_type: &PackType) -> usize {
+    1 + match pack_type {
+        PackType::Group(items, _) => items.iter().map(nodes).sum(),
+        PackType::LengthPrefixed(length, item) => nodes(length) + nodes(item),
+        PackType::Checksum(_, item) => nodes(item),
+        _ => 0,
+    }
+}
@@ -0,0 +1,227 @@
+//! [`TemplateCodec`], framing tokio streams with records of a template.
+use std::io;
+
+use bytes::{Buf, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::stream::{invalid_input, read_record, unpack_error};
+use crate::{PackableArg, Template, TemplateError, Unpacked, UnpackError, DEFAULT_MAX_LENGTH};
+
+/// A [`Decoder`] and [`Encoder`] of records of a template, to frame a stream with
+/// [`Framed`](tokio_util::codec::Framed), [`FramedRead`](tokio_util::codec::FramedRead) or [`FramedWrite`](tokio_util::codec::FramedWrite).
+///
+/// Records are decoded once they are whole: until then, while they end early, before the null byte of `Z*`
+/// or before a position the template moves to, [`Decoder::decode`] returns `Ok(None)`.
+/// The record ends where the template stops reading, so `n/a*` frames strings after their length.
+/// Formats reading whatever is left (`a*`, `C*`, `(nC)*`...) take everything buffered.
+///
+/// Anything made of [`PackableArg`]s is encoded as a record.
+/// Errors are the ones of [`pack_to`](crate::pack_to) and [`unpack_from`](crate::unpack_from),
+/// wrapping the [`PackError`](crate::PackError) or [`UnpackError`].
+///
+/// ```
+/// use futures_util::{SinkExt, StreamExt};
+/// use rust_pack::{PackableArg, TemplateCodec, Unpacked};
+/// use tokio_util::codec::Framed;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> std::io::Result<()> {
+/// let (client, server) = tokio::io::duplex(64);
+/// let mut client = Framed::new(client, TemplateCodec::new("n/a*").unwrap());
+/// let mut server = Framed::new(server, TemplateCodec::new("n/a*").unwrap());
+///
+/// client.send([PackableArg::from("hello")]).await?;
+/// assert_eq!(server.next().await.transpose()?, Some(vec![Unpacked::Bytes(b"hello".to_vec())]));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TemplateCodec {
+    template: Template,
+    max_length: usize,
+}
+
+impl TemplateCodec {
+    pub fn new(template: &str) -> Result<TemplateCodec, TemplateError> {
+        Ok(TemplateCodec::from(Template::compile(template)?))
+    }
+
+    /// Bounds the lengths read before a `/` by `max_length` instead of [`DEFAULT_MAX_LENGTH`], see [`unpack_with_limit`](crate::unpack_with_limit).
+    pub fn with_max_length(self, max_length: usize) -> TemplateCodec {
+        TemplateCodec { max_length, ..self }
+    }
+
+    pub fn template(&self) -> &Template {
+        &self.template
+    }
+}
+
+impl From<Template> for TemplateCodec {
+    fn from(template: Template) -> Self {
+        TemplateCodec { template, max_length: DEFAULT_MAX_LENGTH }
+    }
+}
+
+impl Decoder for TemplateCodec {
+    type Item = Vec<Unpacked>;
+    type Error = io::Error;
+
+    /// Takes the first record out of `src`, what follows where the template ended is left there.
+    /// An empty buffer holds no record, even for templates reading nothing.
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Vec<Unpacked>>> {
+        if src.is_empty() {
+            return Ok(None);
+        }
+        let (record, ended) = read_record(&self.template, &mut &src[..], self.max_length)?;
+        let (record, end) = match record {
+            _ if ended => return Ok(None),
+            Ok(record) => record,
+            Err(UnpackError::Truncated { .. }) => return Ok(None),
+            Err(e) => return Err(unpack_error(e)),
+        };
+        src.advance(end);
+        Ok(Some(record))
+    }
+}
+
+impl<'a, T> Encoder<T> for TemplateCodec where
+    T: IntoIterator<Item=PackableArg<'a>> {
+    type Error = io::Error;
+
+    /// Packs a record of the arguments at the end of `dst`.
+    fn encode(&mut self, args: T, dst: &mut BytesMut) -> io::Result<()> {
+        dst.extend_from_slice(&self.template.pack(args.into_iter()).map_err(invalid_input)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackError;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::io::{duplex, AsyncWriteExt};
+    use tokio_util::codec::{Framed, FramedRead, FramedWrite};
+
+    /// Decodes `stream` as its bytes arrive one at a time.
+    fn decode_bytes(codec: &mut TemplateCodec, stream: &[u8]) -> Vec<Vec<Unpacked>> {
+        let mut buffer = BytesMut::new();
+        let mut records = Vec::new();
+        for byte in stream {
+            buffer.extend_from_slice(&[*byte]);
+            if let Some(record) = codec.decode(&mut buffer).unwrap() {
+                records.push(record);
+            }
+        }
+        assert!(buffer.is_empty());
+        records
+    }
+
+    fn record_error(error: io::Error) -> UnpackError {
+        *error.into_inner().unwrap().downcast::<UnpackError>().unwrap()
+    }
+
+    #[test]
+    fn test_decode_incomplete() {
+        let mut codec = TemplateCodec::new("C Z*").unwrap();
+        let mut buffer = BytesMut::from(&b"\x01he"[..]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        assert_eq!(buffer.len(), 3);
+        let records = decode_bytes(&mut codec, b"\x01he\x00\x02llo\x00");
+        assert_eq!(records, [
+            [Unpacked::Unsigned(1), Unpacked::Bytes(b"he".to_vec())],
+            [Unpacked::Unsigned(2), Unpacked::Bytes(b"llo".to_vec())],
+        ]);
+
+        let mut codec = TemplateCodec::new("C @4 C").unwrap();
+        assert_eq!(codec.decode(&mut BytesMut::from(&[1, 0][..])).unwrap(), None);
+        assert_eq!(decode_bytes(&mut codec, &[1, 0, 0, 0, 2, 3, 0, 0, 0, 4]), [[1, 2], [3, 4]].map(|r| r.map(Unpacked::Unsigned)));
+
+        let mut codec = TemplateCodec::new("C x!4 C").unwrap();
+        assert_eq!(codec.decode(&mut BytesMut::from(&[1, 0][..])).unwrap(), None);
+        assert_eq!(decode_bytes(&mut codec, &[1, 0, 0, 0, 2, 3, 0, 0, 0, 4]), [[1, 2], [3, 4]].map(|r| r.map(Unpacked::Unsigned)));
+
+        let mut codec = TemplateCodec::new("w n/a*").unwrap();
+        assert_eq!(decode_bytes(&mut codec, &[0x81, 0x00, 0, 2, b'o', b'k']), [[Unpacked::Unsigned(128), Unpacked::Bytes(b"ok".to_vec())]]);
+
+        // UTF-8 templates read the whole buffer, and take only their record out of it
+        let mut codec = TemplateCodec::new("U C").unwrap();
+        let mut buffer = BytesMut::new();
+        for (character, kind) in [('\u{263a}', 1), ('é', 2)] {
+            codec.encode([PackableArg::from(character), PackableArg::from(kind)], &mut buffer).unwrap();
+        }
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(vec![Unpacked::Unsigned(0x263a), Unpacked::Unsigned(1)]));
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(vec![Unpacked::Unsigned(0xe9), Unpacked::Unsigned(2)]));
+        assert!(buffer.is_empty());
+        assert_eq!(decode_bytes(&mut codec, "\u{263a}\x01é\x02".as_bytes()).len(), 2);
+
+        // nothing buffered is no record, even when the template would read one
+        let mut codec = TemplateCodec::new("C*").unwrap();
+        assert_eq!(codec.decode(&mut BytesMut::new()).unwrap(), None);
+        assert_eq!(codec.decode(&mut BytesMut::from(&[1, 2][..])).unwrap(), Some(vec![Unpacked::Unsigned(1), Unpacked::Unsigned(2)]));
+
+        let mut codec = TemplateCodec::new("C X2").unwrap();
+        let error = codec.decode(&mut BytesMut::from(&[1][..])).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(record_error(error), UnpackError::PositionOutsideOfData { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_framed() {
+        let (client, server) = duplex(16);
+        let mut client = FramedWrite::new(client, TemplateCodec::new("C Z* n/(a3)").unwrap());
+        let server = FramedRead::new(server, TemplateCodec::new("C Z* n/(a3)").unwrap());
+        let send = async move {
+            for (kind, name) in [(1, "first"), (2, "second"), (3, "")] {
+                client.send([PackableArg::from(kind), PackableArg::from(name), PackableArg::from("abc"), PackableArg::from("def")]).await.unwrap();
+            }
+        };
+        let (records, ()) = tokio::join!(server.map(Result::unwrap).collect::<Vec<_>>(), send);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1], [
+            Unpacked::Unsigned(2), Unpacked::Bytes(b"second".to_vec()), Unpacked::Bytes(b"abc".to_vec()), Unpacked::Bytes(b"def".to_vec()),
+        ]);
+
+        // records split across writes, and two in one write
+        let (mut client, server) = duplex(64);
+        let server = FramedRead::new(server, TemplateCodec::new("U n/a*").unwrap());
+        let send = async {
+            for chunk in [&b"\xe2\x98"[..], b"\xba\x00\x02o", b"k\xc3\xa9\x00\x00\xe2\x98\xba\x00\x01!"] {
+                client.write_all(chunk).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+            client.shutdown().await.unwrap();
+        };
+        let (records, ()) = tokio::join!(server.map(Result::unwrap).collect::<Vec<_>>(), send);
+        assert_eq!(records, [
+            [Unpacked::Unsigned(0x263a), Unpacked::Bytes(b"ok".to_vec())],
+            [Unpacked::Unsigned(0xe9), Unpacked::Bytes(Vec::new())],
+            [Unpacked::Unsigned(0x263a), Unpacked::Bytes(b"!".to_vec())],
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_framed_errors() {
+        let (client, server) = duplex(64);
+        let mut client = Framed::new(client, TemplateCodec::new("N").unwrap());
+        let mut server = Framed::new(server, TemplateCodec::new("N/a*").unwrap().with_max_length(16));
+
+        // more arguments than the template packs
+        let error = client.send([PackableArg::from(1), PackableArg::from(2)]).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(error.into_inner().unwrap().downcast::<PackError>().is_ok());
+
+        client.send([PackableArg::from(17)]).await.unwrap();
+        let error = server.next().await.unwrap().unwrap_err();
+        assert!(matches!(record_error(error), UnpackError::LengthOverLimit { length: 17, limit: 16, .. }));
+
+        // the stream ending within a record
+        let (client, server) = duplex(64);
+        let mut client = Framed::new(client, TemplateCodec::new("n").unwrap());
+        let mut server = Framed::new(server, TemplateCodec::new("N").unwrap());
+        client.send([PackableArg::from(1)]).await.unwrap();
+        drop(client);
+        assert!(server.next().await.unwrap().is_err());
+        assert!(server.next().await.is_none());
+    }
+}
@@ -0,0 +1,1017 @@
+//! [`Packable`] and [`Unpackable`] for Rust primitives, strings and byte slices.
+//! Owned values can also be packed by reference, which is what `#[derive(Pack)]` relies on.
+//!
+//! Every value is first turned into a [`Scalar`], the closest thing Rust has to a Perl scalar,
+//! and the scalar is then converted according to the format character the same way Perl does:
+//! numbers are stringified for string formats, strings are numified for numeric formats,
+//! and integers are truncated to the width of the format.
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::string::{String, ToString};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use crate::{Count, Endianness, Packable, PackError, PackType};
+#[cfg(feature = "alloc")]
+use crate::{Malformed, Packed, Unpackable, Unpacked, UnpackedRef, UnpackError};
+
+pub(crate) enum Scalar<'a> {
+    Signed(i128),
+    Unsigned(u128),
+    Float(f64),
+    Char(char),
+    Bytes(&'a [u8]),
+}
+
+impl Scalar<'_> {
+    /// Perl numeric conversion, wrapping at 128 bits and truncating floats.
+    fn to_integer(&self) -> Result<i128, PackError> {
+        match self {
+            Scalar::Signed(v) => Ok(*v),
+            Scalar::Unsigned(v) => Ok(*v as i128),
+            Scalar::Float(v) => float_to_integer(*v),
+            Scalar::Char(c) => Ok(*c as i128),
+            Scalar::Bytes(b) => match numify(b) {
+                Number::Integer(v) => Ok(v),
+                Number::Float(v) => float_to_integer(v),
+            },
+        }
+    }
+
+    /// Perl numeric conversion for the unsigned varints, which cannot hold negative numbers.
+    fn to_unsigned(&self) -> Result<u128, PackError> {
+        match self {
+            Scalar::Unsigned(v) => Ok(*v),
+            Scalar::Float(v) if *v >= 0.0 && v.is_finite() => Ok(*v as u128),
+            _ => u128::try_from(self.to_integer()?).map_err(|_| PackError::NegativeCompressedInteger),
+        }
+    }
+
+    fn to_float(&self) -> f64 {
+        match self {
+            Scalar::Signed(v) => *v as f64,
+            Scalar::Unsigned(v) => *v as f64,
+            Scalar::Float(v) => *v,
+            Scalar::Char(c) => *c as u32 as f64,
+            Scalar::Bytes(b) => match numify(b) {
+                Number::Integer(v) => v as f64,
+                Number::Float(v) => v,
+            },
+        }
+    }
+
+    /// Perl string conversion, numbers and chars are written into `text`.
+    fn to_bytes<'t>(&'t self, text: &'t mut Text) -> &'t [u8] {
+        // the text of numbers and chars always fits
+        let _ = match self {
+            Scalar::Signed(v) => write!(text, "{v}"),
+            Scalar::Unsigned(v) => write!(text, "{v}"),
+            Scalar::Float(v) => {
+                *text = format_float(*v);
+                Ok(())
+            }
+            Scalar::Char(c) => text.write_char(*c),
+            Scalar::Bytes(b) => return b,
+        };
+        text.as_bytes()
+    }
+}
+
+/// Text of a number or a char, kept on the stack: 128 bit integers and `%.15g` floats take less than 48 bytes.
+pub(crate) struct Text {
+    bytes: [u8; 48],
+    len: usize,
+}
+
+impl Text {
+    pub(crate) fn new() -> Text {
+        Text { bytes: [0; 48], len: 0 }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        // only `str`s are written into it
+        core::str::from_utf8(self.as_bytes()).unwrap()
+    }
+}
+
+impl Write for Text {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.bytes.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+fn float_to_integer(value: f64) -> Result<i128, PackError> {
+    match value.is_finite() {
+        true => Ok(value as i128),
+        false => Err(PackError::NonFiniteInteger),
+    }
+}
+
+pub(crate) enum Number {
+    Integer(i128),
+    Float(f64),
+}
+
+/// Perl numeric value of a string: leading whitespace, then a decimal number, `Inf`, `Infinity` or `NaN`
+/// in any case, anything else is ignored (`"12abc"` is 12, `"abc"` is 0).
+/// Numbers without a fraction or an exponent stay integers so they don't lose precision.
+pub(crate) fn numify(bytes: &[u8]) -> Number {
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+    let bytes = &bytes[start..];
+    let sign = usize::from(matches!(bytes.first(), Some(b'-' | b'+')));
+    let negative = bytes.first() == Some(&b'-');
+    let word = |word: &[u8]| bytes[sign..].get(..word.len()).is_some_and(|w| w.eq_ignore_ascii_case(word));
+    if word(b"inf") {
+        return Number::Float(if negative { f64::NEG_INFINITY } else { f64::INFINITY });
+    }
+    if word(b"nan") {
+        return Number::Float(f64::NAN);
+    }
+    let digits = |from: usize| bytes[from.min(bytes.len())..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let mut end = sign + digits(sign);
+    let mut integer = true;
+    if bytes.get(end) == Some(&b'.') && (end > sign || digits(end + 1) > 0) {
+        end += 1 + digits(end + 1);
+        integer = false;
+    }
+    if end > sign && matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let exponent_sign = usize::from(matches!(bytes.get(end + 1), Some(b'-' | b'+')));
+        if digits(end + 1 + exponent_sign) > 0 {
+            end += 1 + exponent_sign + digits(end + 1 + exponent_sign);
+            integer = false;
+        }
+    }
+    // only ASCII was matched, so this is valid UTF-8
+    let number = core::str::from_utf8(&bytes[..end]).unwrap();
+    match integer {
+        true => {
+            let value = number[sign..].bytes().fold(0i128, |v, b| v.saturating_mul(10).saturating_add((b - b'0') as i128));
+            Number::Integer(if negative { -value } else { value })
+        }
+        false => Number::Float(number.parse().unwrap_or(0.0)),
+    }
+}
+
+/// Perl stringification of a float, which is `printf("%.15g")` with `Inf` and `NaN` for special values.
+pub(crate) fn format_float(value: f64) -> Text {
+    let mut text = Text::new();
+    // `%.15g` floats always fit
+    let _ = write_float(&mut text, value);
+    text
+}
+
+fn write_float(text: &mut Text, value: f64) -> fmt::Result {
+    if value.is_nan() {
+        return text.write_str("NaN");
+    }
+    if value.is_infinite() {
+        return text.write_str(if value > 0.0 { "Inf" } else { "-Inf" });
+    }
+    if value == 0.0 {
+        return text.write_str("0");
+    }
+    let trim = |s: &str| match s.contains('.') {
+        true => s.trim_end_matches('0').trim_end_matches('.').len(),
+        false => s.len(),
+    };
+    let mut scientific = Text::new();
+    write!(scientific, "{:.14e}", value)?;
+    let (mantissa, exponent) = scientific.as_str().split_once('e').unwrap();
+    let exponent = exponent.parse::<i32>().unwrap();
+    match exponent {
+        -4..=14 => {
+            let mut fixed = Text::new();
+            write!(fixed, "{:.*}", (14 - exponent) as usize, value)?;
+            text.write_str(&fixed.as_str()[..trim(fixed.as_str())])
+        }
+        _ => write!(text, "{}e{}{:02}", &mantissa[..trim(mantissa)], if exponent < 0 { '-' } else { '+' }, exponent.abs()),
+    }
+}
+
+/// A double as the bits of an IEEE 754 quadruple precision float, this conversion is exact.
+pub(crate) fn f64_to_quad(value: f64) -> u128 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 63) as u128) << 127;
+    let exponent = ((bits >> 52) & 0x7ff) as u128;
+    let mantissa = (bits & ((1 << 52) - 1)) as u128;
+    match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // subnormal doubles are normal quads
+            let shift = mantissa.leading_zeros() - 75;
+            let exponent = (16383 - 1022 - shift) as u128;
+            sign | exponent << 112 | ((mantissa << shift) & ((1 << 52) - 1)) << 60
+        }
+        0x7ff => sign | 0x7fff << 112 | mantissa << 60,
+        _ => sign | (exponent + 16383 - 1023) << 112 | mantissa << 60,
+    }
+}
+
+/// The bits of an IEEE 754 quadruple precision float rounded to the nearest double.
+#[cfg(feature = "alloc")]
+pub(crate) fn quad_to_f64(bits: u128) -> f64 {
+    let sign = if bits >> 127 == 1 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 112) & 0x7fff) as i32;
+    let mantissa = bits & ((1 << 112) - 1);
+    match exponent {
+        0x7fff if mantissa == 0 => sign * f64::INFINITY,
+        0x7fff => f64::NAN,
+        0 => sign * scale(mantissa as f64, 1 - 16383 - 112),
+        _ => sign * scale((mantissa | 1 << 112) as f64, exponent - 16383 - 112),
+    }
+}
+
+/// `value * 2^exponent` without overflowing the intermediate powers of two.
+#[cfg(feature = "alloc")]
+fn scale(mut value: f64, mut exponent: i32) -> f64 {
+    while exponent > 1000 {
+        value *= pow2(1000);
+        exponent -= 1000;
+    }
+    while exponent < -1000 {
+        value *= pow2(-1000);
+        exponent += 1000;
+    }
+    value * pow2(exponent)
+}
+
+/// `2^exponent` for the exponents of normal doubles, `powi` needs `std`.
+#[cfg(feature = "alloc")]
+fn pow2(exponent: i32) -> f64 {
+    match exponent {
+        1024.. => f64::INFINITY,
+        ..-1022 => 0.0,
+        e => f64::from_bits(((e + 1023) as u64) << 52),
+    }
+}
+
+/// Converts little-endian bytes into the byte order, and back again.
+fn reorder(endianness: Endianness, bytes: &mut [u8]) {
+    if endianness == Endianness::Big || (endianness == Endianness::Native && cfg!(target_endian = "big")) {
+        bytes.reverse();
+    }
+}
+
+/// Packs a single value, the count of numeric formats is ignored as every value is packed on its own.
+#[cfg(feature = "alloc")]
+pub(crate) fn pack_scalar(scalar: Scalar<'_>, pack_type: PackType) -> Result<Packed, PackError> {
+    pack_scalar_with(&scalar, &pack_type, zeroes)
+}
+
+/// Same as [`pack_scalar`], into the start of `buffer`, returning the length of the value.
+pub(crate) fn pack_scalar_into(scalar: Scalar<'_>, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+    let packed = pack_scalar_with(&scalar, pack_type, |size| buffer.get_mut(..size).ok_or(PackError::BufferTooSmall))?;
+    Ok(packed.len())
+}
+
+/// Packs a single value into what `room` returns for its size, every byte of which is written.
+pub(crate) fn pack_scalar_with<R: AsMut<[u8]>>(scalar: &Scalar<'_>, pack_type: &PackType, room: impl FnOnce(usize) -> Result<R, PackError>)
+    -> Result<R, PackError> {
+    let mut text = Text::new();
+    // values of at most 19 bytes are packed here before they are copied
+    let mut small = [0; 19];
+    let size = match pack_type {
+        PackType::StringNullPadded(_) | PackType::AsciiNullPadded(_) | PackType::AscizNullPadded(_) => {
+            let string = scalar.to_bytes(&mut text);
+            let (size, kept) = match (pack_type, pack_type.count()) {
+                (PackType::AscizNullPadded(_), Count::Star) => (string.len() + 1, string.len()),
+                (PackType::AscizNullPadded(_), Count::Exact(c)) => (c, string.len().min(c.saturating_sub(1))),
+                (_, Count::Exact(c)) => (c, string.len().min(c)),
+                (_, Count::Star) => (string.len(), string.len()),
+            };
+            let mut packed = room(size)?;
+            let (kept, padding) = packed.as_mut().split_at_mut(kept);
+            kept.copy_from_slice(&string[..kept.len()]);
+            padding.fill(if matches!(pack_type, PackType::AsciiNullPadded(_)) { b' ' } else { 0 });
+            return Ok(packed);
+        }
+        PackType::BitStringAscending(_) | PackType::BitStringDescending(_) | PackType::HexStringLowFirst(_) | PackType::HexStringHighFirst(_) => {
+            let digits = scalar.to_bytes(&mut text);
+            let count = pack_type.count().or(digits.len());
+            let mut packed = room(count.div_ceil(digits_per_byte(pack_type)))?;
+            packed.as_mut().fill(0);
+            for (i, digit) in digits.iter().take(count).enumerate() {
+                put_digit(packed.as_mut(), i, *digit, pack_type);
+            }
+            return Ok(packed);
+        }
+        PackType::Uuencoded(c) => {
+            let bytes = scalar.to_bytes(&mut text);
+            let line = match c.or(0) {
+                0..=2 => 45,
+                n => (n / 3 * 3).min(63),
+            };
+            let last = match bytes.len() % line {
+                0 => 0,
+                rest => rest.div_ceil(3) * 4 + 2,
+            };
+            let mut packed = room(bytes.len() / line * (line / 3 * 4 + 2) + last)?;
+            uuencode(bytes, line, packed.as_mut());
+            return Ok(packed);
+        }
+        PackType::NullByte(c) => {
+            let mut packed = room(c.or(0))?;
+            packed.as_mut().fill(0);
+            return Ok(packed);
+        }
+        PackType::WideChar(_) | PackType::UnicodeChar(_) => {
+            let character = u32::try_from(scalar.to_integer()?).ok().and_then(char::from_u32).ok_or(PackError::InvalidCharacter)?;
+            character.encode_utf8(&mut small).len()
+        }
+        PackType::BerCompressed(_) => {
+            let size = base128(scalar.to_unsigned()?, &mut small);
+            small[..size].reverse();
+            small[..size - 1].iter_mut().for_each(|d| *d |= 0x80);
+            size
+        }
+        PackType::UnsignedLeb128(_) => leb128(scalar.to_unsigned()?, &mut small),
+        PackType::ZigZagVarint(_) => {
+            let value = scalar.to_integer()?;
+            leb128(((value << 1) ^ (value >> 127)) as u128, &mut small)
+        }
+        PackType::SignedLeb128(_) => {
+            let mut value = scalar.to_integer()?;
+            let mut size = 0;
+            loop {
+                let digit = (value & 0x7f) as u8;
+                value >>= 7;
+                // done once the rest is only the sign, which the last digit carries
+                if (value == 0 && digit & 0x40 == 0) || (value == -1 && digit & 0x40 != 0) {
+                    small[size] = digit;
+                    break size + 1;
+                }
+                small[size] = digit | 0x80;
+                size += 1;
+            }
+        }
+        PackType::Float(_, e) | PackType::Double(_, e) | PackType::PerlFloat(_, e) | PackType::LongDouble(_, e) => {
+            let value = scalar.to_float();
+            let size = match pack_type {
+                PackType::Float(..) => put(&mut small, &(value as f32).to_le_bytes()),
+                PackType::LongDouble(..) => put(&mut small, &f64_to_quad(value).to_le_bytes()),
+                _ => put(&mut small, &value.to_le_bytes()),
+            };
+            reorder(*e, &mut small[..size]);
+            size
+        }
+        _ => {
+            // every other format is an integer
+            let (size, _, endianness) = pack_type.integer_layout().unwrap();
+            // two's complement truncation works the same for signed and unsigned formats
+            put(&mut small, &scalar.to_integer()?.to_le_bytes()[..size]);
+            reorder(endianness, &mut small[..size]);
+            size
+        }
+    };
+    let mut packed = room(size)?;
+    packed.as_mut().copy_from_slice(&small[..size]);
+    Ok(packed)
+}
+
+/// Copies `bytes` to the start of `small`, returning their length.
+fn put(small: &mut [u8], bytes: &[u8]) -> usize {
+    small[..bytes.len()].copy_from_slice(bytes);
+    bytes.len()
+}
+
+/// Perl's `%<bits>` checksum of the values unpacked by `pack_type`: their sum modulo 2^bits,
+/// computed with doubles (and as precise) for float formats and above 64 bits.
+/// Bit strings add up their set bits.
+#[cfg(feature = "alloc")]
+pub(crate) fn checksum(bits: u32, pack_type: &PackType, values: &[UnpackedRef<'_>]) -> Unpacked {
+    let float = bits > 64 || matches!(pack_type, PackType::Float(..) | PackType::Double(..) | PackType::PerlFloat(..) | PackType::LongDouble(..));
+    let set_bits = |digits: &[u8]| digits.iter().filter(|d| **d == b'1').count();
+    if !float {
+        // wraps around like Perl's UV
+        let sum = values.iter().fold(0u64, |sum, value| sum.wrapping_add(match value {
+            UnpackedRef::Signed(v) => *v as u64,
+            UnpackedRef::Unsigned(v) => *v as u64,
+            UnpackedRef::Float(v) => *v as u64,
+            UnpackedRef::Bytes(digits) => set_bits(digits) as u64,
+        }));
+        return Unpacked::Unsigned(if bits < 64 { sum & ((1 << bits) - 1) } else { sum } as u128);
+    }
+    let sum: f64 = values.iter().map(|value| match value {
+        UnpackedRef::Signed(v) => *v as f64,
+        UnpackedRef::Unsigned(v) => *v as f64,
+        UnpackedRef::Float(v) => *v,
+        UnpackedRef::Bytes(digits) => set_bits(digits) as f64,
+    }).sum();
+    // `rem_euclid` and `fract` need `std`
+    let modulus = pow2(bits.min(1024) as i32);
+    let sum = sum % modulus;
+    let ratio = if sum < 0.0 { sum + modulus } else { sum } / modulus;
+    Unpacked::Float(if ratio >= 1.0 { 0.0 } else { ratio } * modulus)
+}
+
+/// Writes the base 128 digits of `value` into `digits`, least significant first, and returns how many there are, at least one.
+fn base128(mut value: u128, digits: &mut [u8; 19]) -> usize {
+    let mut size = 0;
+    loop {
+        digits[size] = (value & 0x7f) as u8;
+        size += 1;
+        value >>= 7;
+        if value == 0 {
+            return size;
+        }
+    }
+}
+
+fn leb128(value: u128, digits: &mut [u8; 19]) -> usize {
+    let size = base128(value, digits);
+    digits[..size - 1].iter_mut().for_each(|d| *d |= 0x80);
+    size
+}
+
+/// Decodes a single varint from the start of `data`, returning it with the number of bytes it takes.
+/// Values which don't fit in 128 bits are out of range.
+#[cfg(feature = "alloc")]
+pub(crate) fn decode_varint(pack_type: &PackType, data: &[u8]) -> Result<(Unpacked, usize), Malformed> {
+    let size = data.iter().position(|b| b & 0x80 == 0).ok_or(Malformed::Truncated { needed: data.len() + 1, available: data.len() })? + 1;
+    let digits = &data[..size];
+    if let PackType::BerCompressed(_) = pack_type {
+        let value = digits.iter().try_fold(0u128, |value, digit| value.checked_mul(128).map(|v| v | (digit & 0x7f) as u128));
+        return Ok((Unpacked::Unsigned(value.ok_or(Malformed::Overflow)?), size));
+    }
+    let signed = matches!(pack_type, PackType::SignedLeb128(_));
+    let mut value = 0u128;
+    for (i, digit) in digits.iter().enumerate() {
+        let digit = (digit & 0x7f) as u128;
+        let shift = 7 * i as u32;
+        // unsigned values may only have zero bits past 128 bits, signed values wrap around
+        let lost = match shift < 128 {
+            true => (digit << shift) >> shift != digit,
+            false => digit != 0,
+        };
+        if lost && !signed {
+            return Err(Malformed::Overflow);
+        }
+        value |= digit.checked_shl(shift).unwrap_or(0);
+    }
+    let value = match pack_type {
+        PackType::SignedLeb128(_) => {
+            let bits = 7 * size as u32;
+            match bits < 128 && digits[size - 1] & 0x40 != 0 {
+                true => Unpacked::Signed((value | u128::MAX << bits) as i128),
+                false => Unpacked::Signed(value as i128),
+            }
+        }
+        PackType::ZigZagVarint(_) => Unpacked::Signed((value >> 1) as i128 ^ -((value & 1) as i128)),
+        _ => Unpacked::Unsigned(value),
+    };
+    Ok((value, size))
+}
+
+/// Decodes a single value of a numeric format from exactly [`PackType::size`] bytes.
+#[cfg(feature = "alloc")]
+pub(crate) fn decode_number(pack_type: &PackType, bytes: &[u8]) -> Unpacked {
+    let mut bytes = bytes.to_vec();
+    match pack_type {
+        PackType::Float(_, e) | PackType::Double(_, e) | PackType::PerlFloat(_, e) | PackType::LongDouble(_, e) => {
+            reorder(*e, &mut bytes);
+            // the size of the data matches the format
+            Unpacked::Float(match pack_type {
+                PackType::Float(..) => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                PackType::LongDouble(..) => quad_to_f64(u128::from_le_bytes(bytes.try_into().unwrap())),
+                _ => f64::from_le_bytes(bytes.try_into().unwrap()),
+            })
+        }
+        _ => {
+            let (size, signed, endianness) = pack_type.integer_layout().unwrap();
+            reorder(endianness, &mut bytes);
+            let negative = signed && bytes[size - 1] & 0x80 != 0;
+            bytes.resize(16, if negative { 0xff } else { 0 });
+            let value = i128::from_le_bytes(bytes.try_into().unwrap());
+            match signed {
+                true => Unpacked::Signed(value),
+                false => Unpacked::Unsigned(value as u128),
+            }
+        }
+    }
+}
+
+/// `len` null bytes, [`PackError::OutOfMemory`] when they can't be allocated.
+#[cfg(feature = "alloc")]
+pub(crate) fn zeroes(len: usize) -> Result<Packed, PackError> {
+    let mut bytes = Vec::new();
+    bytes.try_reserve_exact(len).map_err(|_| PackError::OutOfMemory)?;
+    bytes.resize(len, 0);
+    Ok(bytes)
+}
+
+/// Decodes the UTF-8 character `data` starts with, and returns it with its size.
+#[cfg(feature = "alloc")]
+pub(crate) fn decode_utf8(data: &[u8]) -> Result<(char, usize), Malformed> {
+    let size = match data.first() {
+        None => return Err(Malformed::Truncated { needed: 1, available: 0 }),
+        Some(0x00..=0x7f) => 1,
+        Some(0xc2..=0xdf) => 2,
+        Some(0xe0..=0xef) => 3,
+        Some(0xf0..=0xf4) => 4,
+        Some(_) => return Err(Malformed::Utf8),
+    };
+    let bytes = data.get(..size).ok_or(Malformed::Truncated { needed: size, available: data.len() })?;
+    let text = core::str::from_utf8(bytes).map_err(|_| Malformed::Utf8)?;
+    Ok((text.chars().next().unwrap(), size))
+}
+
+/// Size in bytes of the first `characters` UTF-8 characters of `data`.
+#[cfg(feature = "alloc")]
+pub(crate) fn utf8_length(data: &[u8], characters: usize) -> Result<usize, Malformed> {
+    let mut length = 0;
+    for _ in 0..characters {
+        length += decode_utf8(&data[length..])?.1;
+    }
+    Ok(length)
+}
+
+/// Perl's uuencoding into `packed`, which has its size: every line starts with its length and ends with a newline,
+/// 3 bytes become 4 characters between `!` and `_`, with a backtick for 0.
+fn uuencode(bytes: &[u8], line: usize, packed: &mut [u8]) {
+    let character = |six: u8| match six & 0x3f {
+        0 => b'`',
+        six => b' ' + six,
+    };
+    let mut packed = packed.iter_mut();
+    let mut put = |c: u8| *packed.next().unwrap() = c;
+    for chunk in bytes.chunks(line) {
+        put(character(chunk.len() as u8));
+        for group in chunk.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| group.get(i).copied().unwrap_or(0));
+            for six in [a >> 2, a << 4 | b >> 4, b << 2 | c >> 6, c] {
+                put(character(six));
+            }
+        }
+        put(b'\n');
+    }
+}
+
+/// Decodes uuencoded lines like Perl, up to the first character which cannot start one,
+/// and returns the bytes with the size of the lines read. Missing characters count as 0.
+#[cfg(feature = "alloc")]
+pub(crate) fn uudecode(data: &[u8]) -> (Vec<u8>, usize) {
+    let is_uu = |c: u8| (b' '..b'a').contains(&c);
+    let six = |c: u8| (c - b' ') & 0x3f;
+    let mut result = Vec::with_capacity(data.len() * 3 / 4);
+    let mut position = 0;
+    while position < data.len() && data[position] != b' ' && is_uu(data[position]) {
+        let mut length = six(data[position]) as usize;
+        position += 1;
+        while length > 0 {
+            let [a, b, c, d] = [0; 4].map(|_| match data.get(position) {
+                Some(c) if is_uu(*c) => {
+                    position += 1;
+                    six(*c)
+                }
+                _ => 0,
+            });
+            let group = [a << 2 | b >> 4, b << 4 | c >> 2, c << 6 | d];
+            result.extend_from_slice(&group[..length.min(3)]);
+            length = length.saturating_sub(3);
+        }
+        match (data.get(position), data.get(position + 1)) {
+            (Some(b'\n'), _) => position += 1,
+            // a checksum character ends the line
+            (Some(_), Some(b'\n')) => position += 2,
+            _ => {}
+        }
+    }
+    (result, position)
+}
+
+fn digits_per_byte(pack_type: &PackType) -> usize {
+    match pack_type {
+        PackType::BitStringAscending(_) | PackType::BitStringDescending(_) => 8,
+        _ => 2,
+    }
+}
+
+/// Puts the digit `i` of a bit or hex string into `packed`, which starts zeroed.
+/// Like Perl, only the lowest bit of bit digits is used, so `"1"` is 1 and `"0"` is 0,
+/// and letters are hex digits whatever they are, `g` is 0 and `z` is 3.
+fn put_digit(packed: &mut [u8], i: usize, digit: u8, pack_type: &PackType) {
+    match pack_type {
+        PackType::BitStringAscending(_) => packed[i / 8] |= (digit & 1) << (i % 8),
+        PackType::BitStringDescending(_) => packed[i / 8] |= (digit & 1) << (7 - i % 8),
+        _ => {
+            let nybble = match digit.is_ascii_alphabetic() {
+                true => ((digit & 0xf) + 9) & 0xf,
+                false => digit & 0xf,
+            };
+            let shift = match (pack_type, i % 2) {
+                (PackType::HexStringLowFirst(_), 0) | (PackType::HexStringHighFirst(_), 1) => 0,
+                _ => 4,
+            };
+            packed[i / 2] |= nybble << shift;
+        }
+    }
+}
+
+/// Packs the digits `bytes` holds as a bit or hex string in their place, and returns its size.
+pub(crate) fn pack_digits(bytes: &mut [u8], pack_type: &PackType) -> usize {
+    let per_byte = digits_per_byte(pack_type);
+    let size = bytes.len().div_ceil(per_byte);
+    // a byte is made of digits at or past its place
+    for i in 0..size {
+        let mut packed = [0];
+        for (j, digit) in bytes[i * per_byte..].iter().take(per_byte).enumerate() {
+            put_digit(&mut packed, j, *digit, pack_type);
+        }
+        bytes[i] = packed[0];
+    }
+    size
+}
+
+macro_rules! integer_impls {
+    ($variant:ident, $($t:ty),+) => {$(
+        impl Packable for $t {
+            #[cfg(feature = "alloc")]
+            fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+                pack_scalar(Scalar::$variant(*self as _), pack_type)
+            }
+
+            fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+                pack_scalar_into(Scalar::$variant(*self as _), pack_type, buffer)
+            }
+        }
+
+        impl Packable for &$t {
+            #[cfg(feature = "alloc")]
+            fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+                pack_scalar(Scalar::$variant(**self as _), pack_type)
+            }
+
+            fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+                pack_scalar_into(Scalar::$variant(**self as _), pack_type, buffer)
+            }
+        }
+
+        #[cfg(feature = "alloc")]
+        impl Unpackable for $t {
+            fn unpack(value: Unpacked) -> Result<Self, UnpackError> {
+                let converted = match value {
+                    Unpacked::Signed(v) => <$t>::try_from(v).ok(),
+                    Unpacked::Unsigned(v) => <$t>::try_from(v).ok(),
+                    Unpacked::Float(v) => float_to_integer(v).ok().and_then(|v| <$t>::try_from(v).ok()),
+                    Unpacked::Bytes(b) => match numify(&b) {
+                        Number::Integer(v) => <$t>::try_from(v).ok(),
+                        Number::Float(v) => float_to_integer(v).ok().and_then(|v| <$t>::try_from(v).ok()),
+                    },
+                };
+                converted.ok_or(UnpackError::ValueOutOfRange)
+            }
+        }
+    )+};
+}
+
+integer_impls!(Signed, i8, i16, i32, i64, i128, isize);
+integer_impls!(Unsigned, u8, u16, u32, u64, u128, usize);
+
+macro_rules! float_impls {
+    ($($t:ty),+) => {$(
+        impl Packable for $t {
+            #[cfg(feature = "alloc")]
+            fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+                pack_scalar(Scalar::Float(*self as f64), pack_type)
+            }
+
+            fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+                pack_scalar_into(Scalar::Float(*self as f64), pack_type, buffer)
+            }
+        }
+
+        impl Packable for &$t {
+            #[cfg(feature = "alloc")]
+            fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+                pack_scalar(Scalar::Float(**self as f64), pack_type)
+            }
+
+            fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+                pack_scalar_into(Scalar::Float(**self as f64), pack_type, buffer)
+            }
+        }
+
+        #[cfg(feature = "alloc")]
+        impl Unpackable for $t {
+            fn unpack(value: Unpacked) -> Result<Self, UnpackError> {
+                Ok(match value {
+                    Unpacked::Signed(v) => v as $t,
+                    Unpacked::Unsigned(v) => v as $t,
+                    Unpacked::Float(v) => v as $t,
+                    Unpacked::Bytes(b) => Scalar::Bytes(&b).to_float() as $t,
+                })
+            }
+        }
+    )+};
+}
+
+float_impls!(f32, f64);
+
+impl Packable for bool {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        // Perl's true is 1 and its false is the empty string
+        pack_scalar(if *self { Scalar::Unsigned(1) } else { Scalar::Bytes(b"") }, pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        pack_scalar_into(if *self { Scalar::Unsigned(1) } else { Scalar::Bytes(b"") }, pack_type, buffer)
+    }
+}
+
+impl Packable for &bool {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        Box::new(**self).pack(pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        (**self).pack_into(pack_type, buffer)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Unpackable for bool {
+    fn unpack(value: Unpacked) -> Result<Self, UnpackError> {
+        Ok(match value {
+            Unpacked::Signed(v) => v != 0,
+            Unpacked::Unsigned(v) => v != 0,
+            Unpacked::Float(v) => v != 0.0,
+            Unpacked::Bytes(b) => !b.is_empty() && b != b"0",
+        })
+    }
+}
+
+impl Packable for char {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        pack_scalar(Scalar::Char(*self), pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        pack_scalar_into(Scalar::Char(*self), pack_type, buffer)
+    }
+}
+
+impl Packable for &char {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        pack_scalar(Scalar::Char(**self), pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        pack_scalar_into(Scalar::Char(**self), pack_type, buffer)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Unpackable for char {
+    fn unpack(value: Unpacked) -> Result<Self, UnpackError> {
+        match value {
+            Unpacked::Bytes(b) => {
+                let s = core::str::from_utf8(&b).map_err(|_| UnpackError::InvalidUtf8)?;
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(c),
+                    _ => Err(UnpackError::IncompatibleValue),
+                }
+            }
+            _ => char::from_u32(u32::unpack(value)?).ok_or(UnpackError::ValueOutOfRange),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Packable for String {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        pack_scalar(Scalar::Bytes(self.as_bytes()), pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        pack_scalar_into(Scalar::Bytes(self.as_bytes()), pack_type, buffer)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Packable for &String {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        pack_scalar(Scalar::Bytes(self.as_bytes()), pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        pack_scalar_into(Scalar::Bytes(self.as_bytes()), pack_type, buffer)
+    }
+}
+
+impl Packable for &str {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        pack_scalar(Scalar::Bytes(self.as_bytes()), pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        pack_scalar_into(Scalar::Bytes(self.as_bytes()), pack_type, buffer)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Unpackable for String {
+    fn unpack(value: Unpacked) -> Result<Self, UnpackError> {
+        String::from_utf8(Vec::<u8>::unpack(value)?).map_err(|_| UnpackError::InvalidUtf8)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Packable for Vec<u8> {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        pack_scalar(Scalar::Bytes(&self), pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        pack_scalar_into(Scalar::Bytes(self), pack_type, buffer)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Packable for &Vec<u8> {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        pack_scalar(Scalar::Bytes(self.as_slice()), pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        pack_scalar_into(Scalar::Bytes(self.as_slice()), pack_type, buffer)
+    }
+}
+
+impl Packable for &[u8] {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        pack_scalar(Scalar::Bytes(&self), pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        pack_scalar_into(Scalar::Bytes(self), pack_type, buffer)
+    }
+}
+
+impl<const N: usize> Packable for [u8; N] {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        pack_scalar(Scalar::Bytes(self.as_slice()), pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        pack_scalar_into(Scalar::Bytes(self.as_slice()), pack_type, buffer)
+    }
+}
+
+impl<const N: usize> Packable for &[u8; N] {
+    #[cfg(feature = "alloc")]
+    fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+        pack_scalar(Scalar::Bytes(self.as_slice()), pack_type)
+    }
+
+    fn pack_into(&self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        pack_scalar_into(Scalar::Bytes(self.as_slice()), pack_type, buffer)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Unpackable for Vec<u8> {
+    fn unpack(value: Unpacked) -> Result<Self, UnpackError> {
+        Ok(match value {
+            Unpacked::Bytes(b) => b,
+            Unpacked::Signed(v) => v.to_string().into_bytes(),
+            Unpacked::Unsigned(v) => v.to_string().into_bytes(),
+            Unpacked::Float(v) => format_float(v).as_bytes().to_vec(),
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<const N: usize> Unpackable for [u8; N] {
+    fn unpack(value: Unpacked) -> Result<Self, UnpackError> {
+        Vec::<u8>::unpack(value)?.try_into().map_err(|_| UnpackError::IncompatibleValue)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use crate::*;
+    use super::*;
+
+    #[test]
+    fn test_pack_primitives() {
+        let packed = pack("nCZ*", [PackableArg::from(0x10203u32), PackableArg::from('A'), PackableArg::from("hi")].into_iter());
+        assert_eq!(packed.unwrap(), vec![2, 3, b'A', b'h', b'i', 0]);
+        let packed = pack("a4A4Z3c", [PackableArg::from(42), PackableArg::from(b"ab".as_slice()), PackableArg::from(String::from("xyz")), PackableArg::from("-1")].into_iter());
+        assert_eq!(packed.unwrap(), b"42\0\0ab  xy\0\xff".to_vec());
+        let packed = pack("vCC", [PackableArg::from(-1i64), PackableArg::from(true), PackableArg::from(false)].into_iter());
+        assert_eq!(packed.unwrap(), vec![0xff, 0xff, 1, 0]);
+        for template in ["a18446744073709551615", "A18446744073709551615", "Z18446744073709551615", "a18446744073709551615 U0"] {
+            assert_eq!(pack(template, [PackableArg::from("x")].into_iter()).unwrap_err().root_cause(), &PackError::OutOfMemory);
+        }
+    }
+
+    #[test]
+    fn test_unpack_primitives() {
+        assert_eq!(u16::unpack(Unpacked::Unsigned(513)).unwrap(), 513);
+        assert!(matches!(u8::unpack(Unpacked::Unsigned(513)), Err(UnpackError::ValueOutOfRange)));
+        assert_eq!(i32::unpack(Unpacked::Bytes(b" -17 apples".to_vec())).unwrap(), -17);
+        assert_eq!(char::unpack(Unpacked::Unsigned(65)).unwrap(), 'A');
+        assert_eq!(String::unpack(Unpacked::Unsigned(7)).unwrap(), "7");
+        assert!(!bool::unpack(Unpacked::Bytes(b"0".to_vec())).unwrap());
+        assert_eq!(<[u8; 2]>::unpack(Unpacked::Bytes(vec![1, 2])).unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn test_floats() {
+        let packed = pack("f<d>F<", [PackableArg::from(1.5f32), PackableArg::from(-2), PackableArg::from("1e3")].into_iter()).unwrap();
+        assert_eq!(&packed[..4], &1.5f32.to_le_bytes());
+        assert_eq!(&packed[4..12], &(-2f64).to_be_bytes());
+        assert_eq!(unpack("f<d>F<", &packed).unwrap(), vec![Unpacked::Float(1.5), Unpacked::Float(-2.0), Unpacked::Float(1000.0)]);
+        let packed = pack("D>", [PackableArg::from(1.0)].into_iter()).unwrap();
+        assert_eq!(packed, [0x3f, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for value in [0.1, -3.75e-300, 5e-324, f64::MAX, f64::INFINITY] {
+            assert_eq!(quad_to_f64(f64_to_quad(value)), value);
+        }
+        let values = unpack("d<2", &pack("d<2", ["-inf", "NaN"].map(PackableArg::from).into_iter()).unwrap()).unwrap();
+        assert_eq!(values[0], Unpacked::Float(f64::NEG_INFINITY));
+        assert!(matches!(values[1], Unpacked::Float(v) if v.is_nan()));
+        assert_eq!(pack("N", [PackableArg::from(f64::NAN)].into_iter()).unwrap_err().root_cause(), &PackError::NonFiniteInteger);
+        assert_eq!(pack("C", [PackableArg::from(-1.5)].into_iter()).unwrap(), vec![0xff]);
+        assert_eq!(format_float(0.1 + 0.2).as_str(), "0.3");
+        assert_eq!(format_float(1e21).as_str(), "1e+21");
+        assert_eq!(String::unpack(Unpacked::Float(2.5)).unwrap(), "2.5");
+    }
+
+    #[test]
+    fn test_bit_and_hex_strings() {
+        let packed = pack("b5B8h2H*", ["10110", "10000001", "1a", "deadbeef"].map(PackableArg::from).into_iter()).unwrap();
+        assert_eq!(packed, vec![0b01101, 0x81, 0xa1, 0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(pack("b12", [PackableArg::from("1")].into_iter()).unwrap(), vec![1, 0]);
+        let values = unpack("b5B8h2H*", &packed).unwrap();
+        assert_eq!(values, ["10110", "10000001", "1a", "deadbeef"].map(|s| Unpacked::Bytes(s.as_bytes().to_vec())).to_vec());
+        for template in ["b18446744073709551615", "B18446744073709551615", "h18446744073709551615", "H18446744073709551615"] {
+            assert_eq!(pack(template, [PackableArg::from("1")].into_iter()).unwrap_err().root_cause(), &PackError::OutOfMemory);
+        }
+    }
+
+    #[test]
+    fn test_uuencode() {
+        let data = (0..50u8).collect::<Vec<_>>();
+        let lines: &[u8] = b"M``$\"`P0%!@<(\"0H+#`T.#Q`1$A,4%187&!D:&QP='A\\@(2(C)\"4F)R@I*BLL\n%+2XO,#$`\n";
+        assert_eq!(pack("u", [PackableArg::from(&data)].into_iter()).unwrap(), lines);
+        assert_eq!(pack("u31", [PackableArg::from(&data)].into_iter()).unwrap(), &b">``$\"`P0%!@<(\"0H+#`T.#Q`1$A,4%187&!D:&QP=\n4'A\\@(2(C)\"4F)R@I*BLL+2XO,#$`\n"[..]);
+        assert_eq!(pack("u u", ["Cat", ""].map(PackableArg::from).into_iter()).unwrap(), b"#0V%T\n");
+        assert_eq!(unpack("u", lines).unwrap(), vec![Unpacked::Bytes(data.clone())]);
+        let values = unpack("u C", &[lines.strip_suffix(b"\n").unwrap(), &[7]].concat()).unwrap();
+        assert_eq!(values, vec![Unpacked::Bytes(data.clone()), Unpacked::Unsigned(7)]);
+        assert_eq!(unpack("u", lines.strip_suffix(b"\n").unwrap()).unwrap(), vec![Unpacked::Bytes(data)]);
+        assert_eq!(unpack("u a*", b"#0V%T\nrest").unwrap(), [b"Cat".to_vec(), b"rest".to_vec()].map(Unpacked::Bytes));
+        assert_eq!(unpack("u", b"").unwrap(), vec![Unpacked::Bytes(Vec::new())]);
+    }
+
+    #[test]
+    fn test_varints() {
+        let packed = pack("w3", [0u32, 127, 16384].map(PackableArg::from).into_iter()).unwrap();
+        assert_eq!(packed, vec![0, 0x7f, 0x81, 0x80, 0]);
+        assert_eq!(unpack("w*", &packed).unwrap(), [0, 127, 16384].map(Unpacked::Unsigned));
+        assert_eq!(pack("e2", [300, 1].map(PackableArg::from).into_iter()).unwrap(), vec![0xac, 0x02, 1]);
+        assert_eq!(pack("E3", [-1, 63, -65].map(PackableArg::from).into_iter()).unwrap(), vec![0x7f, 0x3f, 0xbf, 0x7f]);
+        assert_eq!(unpack("E3", &[0x7f, 0x3f, 0xbf, 0x7f]).unwrap(), [-1, 63, -65].map(Unpacked::Signed));
+        assert_eq!(pack("z4", [0, -1, 1, -2].map(PackableArg::from).into_iter()).unwrap(), vec![0, 1, 2, 3]);
+        assert_eq!(unpack("z4", &[0, 1, 2, 3]).unwrap(), [0, -1, 1, -2].map(Unpacked::Signed));
+        for template in ["w", "e"] {
+            let packed = pack(template, [PackableArg::from(u128::MAX)].into_iter()).unwrap();
+            assert_eq!(unpack(template, &packed).unwrap(), vec![Unpacked::Unsigned(u128::MAX)]);
+            assert_eq!(pack(template, [PackableArg::from(-1)].into_iter()).unwrap_err().root_cause(), &PackError::NegativeCompressedInteger);
+        }
+        for value in [i128::MIN, i128::MAX] {
+            for template in ["E", "z"] {
+                let packed = pack(template, [PackableArg::from(value)].into_iter()).unwrap();
+                assert_eq!(unpack(template, &packed).unwrap(), vec![Unpacked::Signed(value)]);
+            }
+        }
+        assert_eq!(pack("w", [PackableArg::from("1e3")].into_iter()).unwrap(), vec![0x87, 0x68]);
+        assert!(matches!(unpack("w", &[0x81; 20]), Err(UnpackError::Truncated { needed: 21, available: 20, .. })));
+        assert!(matches!(unpack("w", &[[0xff; 19].as_slice(), &[0x7f]].concat()), Err(UnpackError::Overflow { .. })));
+        assert!(matches!(unpack("e", &[[0xff; 19].as_slice(), &[0x7f]].concat()), Err(UnpackError::Overflow { .. })));
+        assert_eq!(unpack("e C", &[0x81, 0x80, 0x00, 9]).unwrap(), [1, 9].map(Unpacked::Unsigned));
+        assert_eq!(unpack("w/a*", b"\x03abc").unwrap(), vec![Unpacked::Bytes(b"abc".to_vec())]);
+        assert_eq!(pack("w/a*", [PackableArg::from([b'x'; 200].as_slice())].into_iter()).unwrap()[..3], [0x81, 0x48, b'x']);
+    }
+}
@@ -1,223 +1,1938 @@
-use std::error::Error;
-use std::fmt::{Debug, Display, Formatter};
-use std::str::from_utf8_unchecked;
-
-/// https://perldoc.perl.org/functions/pack
-#[derive(Debug)]
-pub enum PackType {
-    /// A string with arbitrary binary data, will be null padded.
-    StringNullPadded(Option<usize>),
-    /// A text (ASCII) string, will be space padded.
-    AsciiNullPadded(Option<usize>),
-    /// A null-terminated (ASCIZ) string, will be null padded.
-    AscizNullPadded(Option<usize>),
-    // TODO: bit strings - a bit complicated
-    /// A signed char (8-bit) value.
-    SignedChar(Option<usize>),
-    /// An unsigned char (octet) value.
-    UnsignedChar(Option<usize>),
-    // TODO: wchar - a bit complicated
-    /// A signed short (16-bit) value.
-    SignedShort(Option<usize>),
-    /// An unsigned short value.
-    UnsignedShort(Option<usize>),
-    /// A signed long (32-bit) value.
-    SignedLong(Option<usize>),
-    /// An unsigned long value.
-    UnsignedLong(Option<usize>),
-    /// A signed quad (64-bit) value.
-    SignedQuad(Option<usize>),
-    /// An unsigned quad value.
-    UnsignedQuad(Option<usize>),
-    // TODO: integers with compile time check
-    /// An unsigned short (16-bit) in "network" (big-endian) order.
-    UnsignedShortBE(Option<usize>),
-    /// An unsigned long (32-bit) in "network" (big-endian) order.
-    UnsignedLongBE(Option<usize>),
-    /// An unsigned short (16-bit) in "VAX" (little-endian) order.
-    UnsignedShortLE(Option<usize>),
-    /// An unsigned long (32-bit) in "VAX" (little-endian) order.
-    UnsignedLongLE(Option<usize>),
-    // TODO: floats are hard
-    /// A null byte (a.k.a ASCII NUL, "\000", chr(0))
-    NullByte(Option<usize>),
-}
-
-impl TryFrom<&str> for PackType {
-    type Error = PackError;
-
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        if value.is_empty() {
-            return Err(PackError::EmptyFormatCharacter);
-        }
-        let size = match value.len() {
-            1 => None,
-            _ => {
-                match value[1..].parse::<usize>() {
-                    Ok(s) => Some(s),
-                    Err(e) => return Err(PackError::InvalidFormatLengthArgument),
-                }
-            }
-        };
-        // https://perldoc.perl.org/functions/pack
-        match value.chars().next().unwrap() { // we checked the size already
-            'a' => Ok(Self::StringNullPadded(size)),
-            'A' => Ok(Self::AsciiNullPadded(size)),
-            'Z' => Ok(Self::AscizNullPadded(size)),
-            'c' => Ok(Self::SignedChar(size)),
-            'C' => Ok(Self::UnsignedChar(size)),
-            's' => Ok(Self::SignedShort(size)),
-            'S' => Ok(Self::UnsignedShort(size)),
-            'l' => Ok(Self::SignedLong(size)),
-            'L' => Ok(Self::UnsignedLong(size)),
-            'q' => Ok(Self::SignedQuad(size)),
-            'Q' => Ok(Self::UnsignedQuad(size)),
-            'n' => Ok(Self::UnsignedShortBE(size)),
-            'N' => Ok(Self::UnsignedLongBE(size)),
-            'v' => Ok(Self::UnsignedShortLE(size)),
-            'V' => Ok(Self::UnsignedLongLE(size)),
-            'x' => Ok(Self::NullByte(size)),
-            _ => Err(PackError::InvalidFormatCharacter),
-        }
-    }
-}
-
-impl TryFrom<String> for PackType {
-    type Error = PackError;
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        PackType::try_from(value.as_str())
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-pub enum PackError {
-    LeftArgumentIsMissingForTemplate,
-    RightArgumentIsMissingForTemplate,
-    InvalidFormatLengthArgument,
-    EmptyFormatCharacter,
-    InvalidFormatCharacter,
-    EmptyTemplate,
-}
-
-#[derive(Debug, Copy, Clone)]
-pub enum UnpackError {}
-
-impl Display for PackError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "PackError: {}", match self {
-            PackError::LeftArgumentIsMissingForTemplate => "Template size is less then arguments count",
-            PackError::RightArgumentIsMissingForTemplate => "Arguments count is less then template size",
-            PackError::InvalidFormatLengthArgument => "Len for the argument is invalid",
-            PackError::EmptyFormatCharacter => "Format character is empty",
-            PackError::InvalidFormatCharacter => "Format character is not supported",
-            PackError::EmptyTemplate => "Template is empty",
-        })
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+extern crate self as rust_pack;
+
+#[cfg(feature = "alloc")]
+use alloc::borrow::Cow;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::vec;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use core::error::Error;
+#[cfg(feature = "alloc")]
+use core::fmt::{Debug, Display, Formatter};
+use core::iter::{Enumerate, Peekable};
+use core::ops::Range;
+
+#[cfg(feature = "tokio")]
+mod codec;
+mod impls;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "alloc")]
+mod template;
+
+#[cfg(feature = "tokio")]
+pub use codec::TemplateCodec;
+#[cfg(feature = "alloc")]
+pub use rust_pack_macros::{Pack, Unpack};
+#[cfg(feature = "std")]
+pub use stream::{pack_to, unpack_from};
+pub use rust_pack_syntax::{Abi, Count, Endianness, Location, PackError, PackType};
+#[cfg(feature = "alloc")]
+pub use rust_pack_syntax::TemplateError;
+#[cfg(feature = "alloc")]
+use rust_pack_syntax::nodes;
+use rust_pack_syntax::{read, Item, Reader, Token};
+use impls::Scalar;
+#[cfg(feature = "alloc")]
+pub use template::Template;
+
+/// Packs the arguments according to the template, see [`pack()`].
+///
+/// Arguments can be any mix of [`Packable`] values:
+/// `pack!("nCZ*", 513u16, 'A', "name")` is `pack("nCZ*", ...)` without building [`PackableArg`]s by hand.
+///
+/// Literal templates are checked while compiling, both for unsupported format characters
+/// and for the number of arguments:
+///
+/// ```compile_fail
+/// let packed = rust_pack::pack!("nCy", 1, 2, 3);
+/// ```
+///
+/// ```compile_fail
+/// let packed = rust_pack::pack!("nC", 1);
+/// ```
+///
+/// The macros parse templates with the same parser as [`Template::parse`], so a template they accept
+/// doesn't fail once packing:
+///
+/// ```compile_fail
+/// let packed = rust_pack::pack!("C n/C0", 1);
+/// ```
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! pack {
+    ($template:literal $(, $arg:expr)* $(,)?) => {
+        $crate::__private::pack_checked!($crate, $template $(, $arg)*)
+    };
+    ($template:expr $(, $arg:expr)* $(,)?) => {
+        $crate::pack($template, [$($crate::PackableArg::from($arg)),*].into_iter())
+    };
+}
+
+/// Unpacks the data according to a literal template, see [`unpack()`].
+///
+/// Unlike [`unpack()`] this returns a tuple typed after the template, so
+/// `unpack!("nCZ*", data)` is a `Result<(u16, u8, String), UnpackError>`.
+/// Templates producing a single value return that value instead of a tuple.
+/// Like with [`pack!`], the template is checked while compiling:
+///
+/// ```compile_fail
+/// let value = rust_pack::unpack!("nQ2k", [0u8; 18]);
+/// ```
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! unpack {
+    ($template:literal, $data:expr $(,)?) => {
+        $crate::__private::unpack_typed!($crate, $template, $data)
+    };
+}
+
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::string::String;
+    pub use alloc::vec::Vec;
+    pub use rust_pack_macros::{pack_checked, unpack_typed};
+
+    use alloc::boxed::Box;
+
+    use crate::{compiled, unpack_checked, Location, Unpackable, Unpacked, UnpackedRef, UnpackError, DEFAULT_MAX_LENGTH};
+
+    /// Values of `unpack!` and `#[derive(Unpack)]`, which can't be converted located at their item.
+    pub struct Values {
+        values: alloc::vec::IntoIter<Unpacked>,
+        /// Where every value is, then where the template ended.
+        locations: Vec<Location>,
+        next: usize,
+    }
+
+    impl Values {
+        pub fn unpack(template: &str, packed: &[u8]) -> Result<Values, UnpackError> {
+            let template = compiled(template).map_err(UnpackError::InvalidTemplate)?;
+            let mut locations = Vec::new();
+            let values = unpack_checked(&template, packed, DEFAULT_MAX_LENGTH, &[], false, Some(&mut locations))?;
+            Ok(Values { values: values.into_iter().map(UnpackedRef::into_owned).collect::<Vec<_>>().into_iter(), locations, next: 0 })
+        }
+
+        pub fn next_value<T: Unpackable>(&mut self) -> Result<T, UnpackError> {
+            let value = self.next;
+            self.next += 1;
+            let at = self.locations[value.min(self.locations.len() - 1)].clone();
+            let cause = match self.values.next() {
+                Some(unpacked) => T::unpack(unpacked),
+                None => Err(UnpackError::NotEnoughData),
+            };
+            cause.map_err(|cause| UnpackError::Value { at, value, cause: Box::new(cause) })
+        }
     }
 }
 
-impl Display for UnpackError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        todo!()
+/// Errors of the data carry the [`Location`] of the item which could not read it,
+/// the others come from converting values into Rust types, see [`Unpackable`],
+/// and are located as [`UnpackError::Value`] by `unpack!` and `#[derive(Unpack)]`, see [`UnpackError::root_cause`].
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnpackError {
+    InvalidTemplate(PackError),
+    /// Fewer values were unpacked than asked for.
+    NotEnoughData,
+    ValueOutOfRange,
+    InvalidUtf8,
+    IncompatibleValue,
+    /// The data ends inside the item, which reads `needed` bytes from its start where only `available` are left.
+    Truncated { at: Location, needed: usize, available: usize },
+    /// A `U`, or a string or char format reading characters, meets bytes which are not UTF-8.
+    MalformedUtf8 { at: Location },
+    /// A varint, or a length before `/`, doesn't fit in its type.
+    Overflow { at: Location },
+    LengthOverLimit { at: Location, length: usize, limit: usize },
+    PositionOutsideOfData { at: Location },
+    /// The template ends before the data, see [`Template::unpack_exact`].
+    TrailingData { at: Location, remaining: usize },
+    /// A value differs from the one expected, a magic number or a checksum, see [`Template::unpack_expecting`].
+    Mismatch { at: Location, expected: Unpacked, actual: Unpacked },
+    /// The value at index `value` could not be converted, or is missing, located at the item which unpacked it
+    /// or at the end of the template. Values of groups and `/` are located at the item of the template holding them.
+    Value { at: Location, value: usize, cause: Box<UnpackError> },
+}
+
+#[cfg(feature = "alloc")]
+impl UnpackError {
+    /// The error itself, without the value it happened in.
+    pub fn root_cause(&self) -> &UnpackError {
+        match self {
+            UnpackError::Value { cause, .. } => cause.root_cause(),
+            e => e,
+        }
     }
 }
 
-impl Error for UnpackError {}
+/// Why a format could not read the data, before [`unpack_private`] locates it.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Malformed {
+    /// `needed` bytes are read where `available` are left, both from the data the format was given.
+    Truncated { needed: usize, available: usize },
+    Utf8,
+    Overflow,
+}
+
+#[cfg(feature = "alloc")]
+impl Display for UnpackError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            UnpackError::InvalidTemplate(e) => write!(f, "UnpackError: {}", e),
+            UnpackError::NotEnoughData => write!(f, "UnpackError: Data holds less values then requested"),
+            UnpackError::ValueOutOfRange => write!(f, "UnpackError: Value does not fit into the requested type"),
+            UnpackError::InvalidUtf8 => write!(f, "UnpackError: Value is not a valid UTF-8 string"),
+            UnpackError::IncompatibleValue => write!(f, "UnpackError: Value can not be converted into the requested type"),
+            UnpackError::Truncated { at, needed, available } =>
+                write!(f, "UnpackError: Data is shorter then template requires, {} bytes needed and {} left {}", needed, available, at),
+            UnpackError::MalformedUtf8 { at } => write!(f, "UnpackError: Data is not a valid UTF-8 string {}", at),
+            UnpackError::Overflow { at } => write!(f, "UnpackError: Value does not fit into its format {}", at),
+            UnpackError::LengthOverLimit { at, length, limit } =>
+                write!(f, "UnpackError: Length {} before `/` is over the limit of {} {}", length, limit, at),
+            UnpackError::PositionOutsideOfData { at } => write!(f, "UnpackError: Position is outside of the data {}", at),
+            UnpackError::TrailingData { at, remaining } => write!(f, "UnpackError: {} bytes are left after the template {}", remaining, at),
+            UnpackError::Mismatch { at, expected, actual } =>
+                write!(f, "UnpackError: Expected {:?} and found {:?} {}", expected, actual, at),
+            UnpackError::Value { at, value, cause } => write!(f, "{} for value {} {}", cause, value, at),
+        }
+    }
+}
 
-impl Error for PackError {}
+#[cfg(feature = "alloc")]
+impl Error for UnpackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            UnpackError::InvalidTemplate(e) => Some(e),
+            UnpackError::Value { cause, .. } => Some(cause.as_ref()),
+            _ => None,
+        }
+    }
+}
 
+#[cfg(feature = "alloc")]
 pub type Packed = Vec<u8>; // TODO: maybe some other type will fit better?
 
+/// A value which packs into the formats of a template, converting itself like Perl does.
+/// Errors of the implementation which aren't a [`PackError`] are wrapped with [`PackError::other`]:
+///
+/// ```
+/// use rust_pack::{pack, Packable, PackableArg, PackError, PackType, Packed};
+///
+/// #[derive(Debug)]
+/// struct TooLong;
+///
+/// impl std::fmt::Display for TooLong {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         write!(f, "name is longer than 8 bytes")
+///     }
+/// }
+///
+/// impl std::error::Error for TooLong {}
+///
+/// struct Name(&'static str);
+///
+/// impl Packable for Name {
+///     fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+///         match self.0.len() {
+///             0..=8 => Box::new(self.0).pack(pack_type),
+///             _ => Err(PackError::other(TooLong)),
+///         }
+///     }
+/// }
+///
+/// let e = pack("n a8", [PackableArg::from(1), PackableArg::from(Name("much too long"))].into_iter()).unwrap_err();
+/// assert!(matches!(e, PackError::Item { argument: Some(1), ref at, .. } if at.span == (2..4)));
+/// let cause = std::error::Error::source(&e).unwrap();
+/// assert_eq!(std::error::Error::source(cause).unwrap().to_string(), "name is longer than 8 bytes");
+/// ```
 pub trait Packable {
+    #[cfg(feature = "alloc")]
     fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError>;
+
+    /// Same as [`Packable::pack`], into the start of `buffer` without allocating, returning the length of the value.
+    /// Values which don't fit are [`PackError::BufferTooSmall`]. This is how [`pack_into`] packs its arguments,
+    /// values which don't implement it are [`PackError::WrongArgumentType`] there.
+    fn pack_into(&self, _pack_type: &PackType, _buffer: &mut [u8]) -> Result<usize, PackError> {
+        Err(PackError::WrongArgumentType)
+    }
 }
 
+#[cfg(feature = "alloc")]
 pub trait Unpackable {
-    fn unpack(data: &[u8], pack_type: PackType) -> Result<Self, UnpackError> where Self: Sized;
+    fn unpack(value: Unpacked) -> Result<Self, UnpackError> where Self: Sized;
 }
 
-pub struct PackableArg {
-    inner: Box<dyn Packable>,
+/// A record packed with a fixed template, usually implemented with `#[derive(Pack)]`:
+///
+/// ```
+/// use rust_pack::Pack;
+///
+/// #[derive(Pack)]
+/// struct Packet {
+///     #[pack("v")]
+///     size: u16,
+///     #[pack("c")]
+///     command: u8,
+///     #[pack("Z*")]
+///     argument: String,
+/// }
+///
+/// assert_eq!(Packet::TEMPLATE, "v\nc\nZ*");
+/// let p = Packet { size: 3, command: 1, argument: "ls".to_string() };
+/// assert_eq!(p.pack().unwrap(), b"\x03\x00\x01ls\x00");
+/// ```
+#[cfg(feature = "alloc")]
+pub trait Pack {
+    /// Template made of the formats of every field.
+    const TEMPLATE: &'static str;
+    fn pack(&self) -> Result<Packed, PackError>;
 }
 
-pub fn pack<T>(template: &str, args: T) -> Result<Packed, PackError> where
-    T: Iterator<Item=PackableArg> {
-    // very stupid version
-    // one day I will write something better
-    if template.is_empty() {
+/// A record unpacked with a fixed template, usually implemented with `#[derive(Unpack)]`.
+#[cfg(feature = "alloc")]
+pub trait Unpack {
+    /// Template made of the formats of every field.
+    const TEMPLATE: &'static str;
+    fn unpack(data: &[u8]) -> Result<Self, UnpackError> where Self: Sized;
+}
+
+/// A single value decoded by [`unpack()`].
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unpacked {
+    /// Produced by `a`, `A`, `Z`, `u`, and by `b`, `B`, `h` and `H` as ASCII digits.
+    Bytes(Vec<u8>),
+    /// Produced by `c`, `s`, `l`, `q`, `i`, `j`, `n!`, `N!`, `v!`, `V!`, and by the signed varints `E` and `z`.
+    Signed(i128),
+    /// Produced by `C`, `S`, `L`, `Q`, `I`, `J`, `n`, `N`, `v`, `V`, by the unsigned varints `w` and `e`,
+    /// and by `W` and `U` as code points.
+    Unsigned(u128),
+    /// Produced by `f`, `d`, `F` and `D`.
+    Float(f64),
+}
+
+#[cfg(feature = "alloc")]
+impl Unpackable for Unpacked {
+    fn unpack(value: Unpacked) -> Result<Self, UnpackError> {
+        Ok(value)
+    }
+}
+
+/// A single value decoded by [`unpack_ref`], strings borrowing the data they were read from.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnpackedRef<'a> {
+    /// Borrowed from the data for `a`, `A` and `Z`, owned for the digits of `b`, `B`, `h`, `H` and the bytes decoded by `u`.
+    Bytes(Cow<'a, [u8]>),
+    Signed(i128),
+    Unsigned(u128),
+    Float(f64),
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> UnpackedRef<'a> {
+    /// The same value owning its bytes, copied only when they are borrowed.
+    pub fn into_owned(self) -> Unpacked {
+        match self {
+            UnpackedRef::Bytes(bytes) => Unpacked::Bytes(bytes.into_owned()),
+            UnpackedRef::Signed(v) => Unpacked::Signed(v),
+            UnpackedRef::Unsigned(v) => Unpacked::Unsigned(v),
+            UnpackedRef::Float(v) => Unpacked::Float(v),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<Unpacked> for UnpackedRef<'_> {
+    fn from(value: Unpacked) -> Self {
+        match value {
+            Unpacked::Bytes(bytes) => UnpackedRef::Bytes(Cow::Owned(bytes)),
+            Unpacked::Signed(v) => UnpackedRef::Signed(v),
+            Unpacked::Unsigned(v) => UnpackedRef::Unsigned(v),
+            Unpacked::Float(v) => UnpackedRef::Float(v),
+        }
+    }
+}
+
+/// The bytes borrowed from the data, [`UnpackError::IncompatibleValue`] for numbers and for owned bytes.
+#[cfg(feature = "alloc")]
+impl<'a> TryFrom<UnpackedRef<'a>> for &'a [u8] {
+    type Error = UnpackError;
+
+    fn try_from(value: UnpackedRef<'a>) -> Result<Self, Self::Error> {
+        match value {
+            UnpackedRef::Bytes(Cow::Borrowed(bytes)) => Ok(bytes),
+            _ => Err(UnpackError::IncompatibleValue),
+        }
+    }
+}
+
+/// The text borrowed from the data, which must be UTF-8.
+#[cfg(feature = "alloc")]
+impl<'a> TryFrom<UnpackedRef<'a>> for &'a str {
+    type Error = UnpackError;
+
+    fn try_from(value: UnpackedRef<'a>) -> Result<Self, Self::Error> {
+        core::str::from_utf8(<&[u8]>::try_from(value)?).map_err(|_| UnpackError::InvalidUtf8)
+    }
+}
+
+#[cfg(feature = "alloc")]
+pub struct PackableArg<'a> {
+    inner: Box<dyn Packable + 'a>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, T: Packable + 'a> From<T> for PackableArg<'a> {
+    fn from(value: T) -> Self {
+        PackableArg { inner: Box::new(value) }
+    }
+}
+
+/// Packs the arguments according to the template.
+///
+/// The template is parsed on every call, unless the `cache` feature is on:
+/// then templates are compiled once into a global cache, see [`Template::compile`] to keep one yourself.
+#[cfg(feature = "alloc")]
+pub fn pack<'a, T>(template: &str, args: T) -> Result<Packed, PackError> where
+    T: Iterator<Item=PackableArg<'a>> {
+    compiled(template)?.pack(args)
+}
+
+/// Same as [`pack()`], with the sizes of the native formats (`i`, `I`, `j`, `J`, `s!`, `S!`, `l!`, `L!`)
+/// taken from `abi` to pack data for another platform.
+#[cfg(feature = "alloc")]
+pub fn pack_with_abi<'a, T>(template: &str, args: T, abi: Abi) -> Result<Packed, PackError> where
+    T: Iterator<Item=PackableArg<'a>> {
+    pack_private(&Template::parse_with_abi(template, abi)?, args)
+}
+
+#[cfg(feature = "cache")]
+fn compiled(template: &str) -> Result<std::sync::Arc<Template>, PackError> {
+    template::cached(template).map_err(PackError::from)
+}
+
+#[cfg(all(feature = "alloc", not(feature = "cache")))]
+fn compiled(template: &str) -> Result<Template, PackError> {
+    Template::compile(template).map_err(PackError::from)
+}
+
+/// Replaces the native formats with the fixed size formats of the same size in `abi`.
+#[cfg(feature = "alloc")]
+fn apply_abi(items: &mut [PackType], abi: Abi) -> Result<(), PackError> {
+    for item in items {
+        let (size, signed, endianness) = match item {
+            PackType::Group(items, _) => {
+                apply_abi(items, abi)?;
+                continue;
+            }
+            PackType::LengthPrefixed(length, item) => {
+                apply_abi(core::slice::from_mut(&mut **length), abi)?;
+                apply_abi(core::slice::from_mut(&mut **item), abi)?;
+                continue;
+            }
+            PackType::Checksum(_, item) => {
+                apply_abi(core::slice::from_mut(&mut **item), abi)?;
+                continue;
+            }
+            PackType::NativeSignedShort(_, e) => (abi.short, true, *e),
+            PackType::NativeUnsignedShort(_, e) => (abi.short, false, *e),
+            PackType::SignedInteger(_, e) => (abi.int, true, *e),
+            PackType::UnsignedInteger(_, e) => (abi.int, false, *e),
+            PackType::NativeSignedLong(_, e) => (abi.long, true, *e),
+            PackType::NativeUnsignedLong(_, e) => (abi.long, false, *e),
+            PackType::PerlSignedInteger(_, e) => (abi.iv, true, *e),
+            PackType::PerlUnsignedInteger(_, e) => (abi.iv, false, *e),
+            _ => continue,
+        };
+        let count = item.count();
+        *item = match (size, signed) {
+            (1, true) => PackType::SignedChar(count),
+            (1, false) => PackType::UnsignedChar(count),
+            (2, true) => PackType::SignedShort(count, endianness),
+            (2, false) => PackType::UnsignedShort(count, endianness),
+            (4, true) => PackType::SignedLong(count, endianness),
+            (4, false) => PackType::UnsignedLong(count, endianness),
+            (8, true) => PackType::SignedQuad(count, endianness),
+            (8, false) => PackType::UnsignedQuad(count, endianness),
+            _ => return Err(PackError::UnsupportedNativeSize),
+        };
+    }
+    Ok(())
+}
+
+#[cfg(feature = "alloc")]
+fn pack_private<'a, T>(template: &Template, args: T) -> Result<Packed, PackError> where
+    T: Iterator<Item=PackableArg<'a>> {
+    // TODO: 4k slab is okay or not?
+    pack_output(Tree::new(template), args, Packed::with_capacity(4096)).map_err(Interrupted::into_pack_error)
+}
+
+/// Same as [`pack()`], into `buffer`, returning the length of the string.
+///
+/// Nothing is allocated: the template is read as the arguments are packed, and the arguments pack themselves
+/// into `buffer` with [`Packable::pack_into`]. A string which doesn't fit is [`PackError::BufferTooSmall`].
+/// `buffer` also needs room for the whole text of the strings of character mode, and for the digits of bit and hex strings
+/// after a `/`, before they are cut to their count. Errors aren't located in the template, which would allocate.
+///
+/// ```
+/// use rust_pack::{pack_into, Packable, PackError};
+///
+/// let mut buffer = [0; 8];
+/// let args: [&dyn Packable; 2] = [&"abc", &7u8];
+/// assert_eq!(pack_into("n/a* C", args.into_iter(), &mut buffer), Ok(6));
+/// assert_eq!(&buffer[..6], b"\0\x03abc\x07");
+/// assert_eq!(pack_into("n/a* C", args.into_iter(), &mut buffer[..5]), Err(PackError::BufferTooSmall));
+/// ```
+pub fn pack_into<'a, T>(template: &str, args: T, buffer: &mut [u8]) -> Result<usize, PackError> where
+    T: Iterator<Item=&'a dyn Packable> {
+    // the template is read once for its errors, so that they come before those of the arguments
+    let mut items = read(template, Some(Abi::NATIVE)).peekable();
+    if items.peek().is_none() {
         return Err(PackError::EmptyTemplate);
     }
-    let mut packed_template: Vec<PackType> = Vec::with_capacity(template.len()); // predict
-    let binding = template.chars().filter(|f| f.is_ascii_alphanumeric()).collect::<String>();
-    let t = binding.as_bytes();
-    let mut end = t.len();
-    let mut start = t.len() - 1;
-    loop {
-        if t[start].is_ascii_alphabetic() {
-            let f = &t[start..end];
-            packed_template.push(PackType::try_from(unsafe { from_utf8_unchecked(f) })?); // it's safe as we just converted it from valid utf8
-            end = start;
-        }
-        if start == 0 {
-            break;
-        }
-        start -= 1;
-    }
-    pack_private(packed_template.into_iter().rev(), args)
-}
-
-fn pack_private<X, T>(mut template: X, mut args: T) -> Result<Packed, PackError> where
-    X: Iterator<Item=PackType>,
-    T: Iterator<Item=PackableArg> {
-    let mut result = Packed::with_capacity(4096); // TODO: 4k slab is okay or not?
-    loop {
-        let packaging = template.next();
-        let argument = args.next();
-        match (packaging, argument) {
-            (Some(p), Some(a)) => {
-                match a.inner.pack(p) {
-                    Ok(mut data) => {
-                        result.append(&mut data);
-                    }
-                    Err(e) => return Err(e),
+    items.try_for_each(|item| item.map(drop).map_err(|(e, _)| e))?;
+    pack_source(template, args, buffer)
+}
+
+/// Packs a template known to be valid into `buffer`, reading it along the way.
+fn pack_source<'a, T>(template: &str, args: T, buffer: &mut [u8]) -> Result<usize, PackError> where
+    T: Iterator<Item=&'a dyn Packable> {
+    let output = pack_output(read(template, Some(Abi::NATIVE)), args, Buffer { bytes: buffer, len: 0 }).map_err(Interrupted::into_pack_error)?;
+    Ok(output.len)
+}
+
+/// Packs the items into `output` and returns it.
+fn pack_output<'t, I: Items<'t>, A: Argument<O>, T: Iterator<Item=A>, O: Output>(items: I, args: T, output: O) -> Result<O, Interrupted> {
+    let mut args = args.enumerate().peekable();
+    let utf8 = is_utf8(&items);
+    let mut packing = Packing {
+        result: output,
+        held: 0,
+        utf8,
+        characters: utf8 && !starts_with_unicode(&items),
+        taken: 0,
+    };
+    pack_items(items, &Groups { start: 0, outer: None }, &mut args, &mut packing)?;
+    match args.peek() {
+        Some(_) => Err(PackError::LeftArgumentIsMissingForTemplate.into()),
+        None => Ok(packing.result),
+    }
+}
+
+/// Whether the template makes a UTF-8 string, by starting with `U` or holding `U0`.
+fn is_utf8<'t, I: Items<'t>>(items: &I) -> bool {
+    fn holds_byte_mode<'t, I: Items<'t>>(mut items: I) -> bool {
+        while let Some(Ok(entry)) = items.next_item() {
+            let holds = match entry.node {
+                Node::Format(PackType::ByteMode) => true,
+                Node::Group(items, _) => holds_byte_mode(items),
+                _ => false,
+            };
+            if holds {
+                return true;
+            }
+        }
+        false
+    }
+    starts_with_unicode(items) || holds_byte_mode(items.clone())
+}
+
+fn starts_with_unicode<'t, I: Items<'t>>(items: &I) -> bool {
+    matches!(items.clone().next_item(), Some(Ok(Entry { node: Node::Format(PackType::UnicodeChar(_)), .. })))
+}
+
+/// The items of a template as they are packed: the tree of a [`Template`], or a [`Reader`] reading its source.
+trait Items<'t>: Clone {
+    fn next_item(&mut self) -> Option<Result<Entry<'t, Self>, PackError>>;
+}
+
+/// An item of [`Items`], with where it is in [`Template::spans`] and in the source.
+struct Entry<'t, I> {
+    node: Node<I>,
+    index: usize,
+    span: Range<usize>,
+    /// The item in the tree of the template, which errors are located with. Reading the source doesn't locate them,
+    /// it would allocate.
+    tree: Option<&'t PackType>,
+}
+
+enum Node<I> {
+    Format(PackType),
+    Group(I, Count),
+    /// The format of the length, and the item following the `/`.
+    LengthPrefixed(PackType, I),
+    Checksum,
+}
+
+/// The items of the tree of a [`Template`].
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+struct Tree<'t> {
+    items: core::slice::Iter<'t, PackType>,
+    /// Where the next item is in [`Template::spans`].
+    index: usize,
+    spans: &'t [Range<usize>],
+}
+
+#[cfg(feature = "alloc")]
+impl<'t> Tree<'t> {
+    fn new(template: &'t Template) -> Tree<'t> {
+        Tree { items: template.items().iter(), index: 0, spans: template.spans() }
+    }
+
+    fn of(&self, items: &'t [PackType], index: usize) -> Tree<'t> {
+        Tree { items: items.iter(), index, spans: self.spans }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'t> Items<'t> for Tree<'t> {
+    fn next_item(&mut self) -> Option<Result<Entry<'t, Self>, PackError>> {
+        let item = self.items.next()?;
+        let index = self.index;
+        self.index += nodes(item);
+        let node = match item {
+            PackType::Group(items, count) => Node::Group(self.of(items, index + 1), *count),
+            PackType::LengthPrefixed(length, item) => {
+                Node::LengthPrefixed((**length).clone(), self.of(core::slice::from_ref(item), index + 1 + nodes(length)))
+            }
+            PackType::Checksum(..) => Node::Checksum,
+            p => Node::Format(p.clone()),
+        };
+        Some(Ok(Entry { node, index, span: self.spans.get(index).cloned().unwrap_or_default(), tree: Some(item) }))
+    }
+}
+
+impl<'t> Items<'t> for Reader<'_> {
+    fn next_item(&mut self) -> Option<Result<Entry<'t, Self>, PackError>> {
+        let Item { token, index, span } = match self.next()? {
+            Ok(item) => item,
+            Err((e, _)) => return Some(Err(e)),
+        };
+        let node = match token {
+            Token::Format(p) => Node::Format(p),
+            Token::Group(items, count) => Node::Group(items, count),
+            Token::LengthPrefixed(mut length, item) => match length.next() {
+                Some(Ok(Item { token: Token::Format(length), .. })) => Node::LengthPrefixed(length, item),
+                _ => return Some(Err(PackError::InvalidLengthItem)),
+            },
+            Token::Checksum(..) => Node::Checksum,
+        };
+        Some(Ok(Entry { node, index, span, tree: None }))
+    }
+}
+
+/// Output of [`pack()`], with the state the template changes along the way.
+struct Packing<O> {
+    result: O,
+    /// Number of `/` lengths waiting for the item following them, `result` can't be flushed until they are packed.
+    held: usize,
+    /// Whether the string is UTF-8, see [`PackType::ByteMode`].
+    utf8: bool,
+    /// Whether string and char formats handle characters of the UTF-8 string rather than bytes, see [`PackType::CharacterMode`].
+    characters: bool,
+    /// Number of arguments taken so far.
+    taken: usize,
+}
+
+impl<O: Output> Packing<O> {
+    /// Takes the next argument, counting it.
+    fn next_argument<A, T: Iterator<Item=A>>(&mut self, args: &mut Peekable<Enumerate<T>>) -> Option<A> {
+        let (i, argument) = args.next()?;
+        self.taken = i + 1;
+        Some(argument)
+    }
+
+    /// Adds where it happened to an error of the item at `index` of the tree, which started at `offset` with `taken` arguments taken.
+    /// Errors of the items of a group or a `/` are already located.
+    #[cfg(feature = "alloc")]
+    fn locate(&self, e: Interrupted, tree: Option<&PackType>, index: usize, span: Range<usize>, offset: usize, taken: usize) -> Interrupted {
+        let (e, pack_type) = match (e, tree) {
+            (Interrupted::Pack(e @ PackError::Item { .. }), _) | (Interrupted::Pack(e), None) => return Interrupted::Pack(e),
+            (Interrupted::Pack(e), Some(pack_type)) => (e, pack_type),
+            #[cfg(feature = "std")]
+            (Interrupted::Write(e), _) => return Interrupted::Write(e),
+        };
+        let argument = match e {
+            PackError::RightArgumentIsMissingForTemplate => Some(self.taken),
+            _ if self.taken > taken => Some(self.taken - 1),
+            _ => None,
+        };
+        let at = Location { offset, item: index, span };
+        Interrupted::Pack(PackError::Item { at, argument, pack_type: Box::new(pack_type.clone()), cause: Box::new(e) })
+    }
+
+    /// Without `alloc` there is no tree, and no [`PackError::Item`] to locate errors with.
+    #[cfg(not(feature = "alloc"))]
+    fn locate(&self, e: Interrupted, _tree: Option<&PackType>, _index: usize, _span: Range<usize>, _offset: usize, _taken: usize) -> Interrupted {
+        e
+    }
+
+    /// Lets the output hand over what is packed, unless a `/` still needs it.
+    fn flush(&mut self) -> Result<(), Interrupted> {
+        match self.held {
+            0 => self.result.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Start of the string, then of every group being packed, each of them knowing the group holding it.
+struct Groups<'g> {
+    start: usize,
+    outer: Option<&'g Groups<'g>>,
+}
+
+impl Groups<'_> {
+    /// Start of the `n`th group counting outward from this one, which is the first, 0 past the outermost one.
+    fn nth(&self, n: usize) -> usize {
+        let mut group = self;
+        for _ in 1..n {
+            match group.outer {
+                Some(outer) => group = outer,
+                None => return 0,
+            }
+        }
+        group.start
+    }
+}
+
+/// Where [`Packing`] puts the string, positions count from the start of the string.
+trait Output {
+    fn len(&self) -> usize;
+
+    fn put(&mut self, bytes: &[u8]) -> Result<(), PackError> {
+        self.room(bytes.len())?.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Adds `size` bytes to the string, and returns them to be written over.
+    fn room(&mut self, size: usize) -> Result<&mut [u8], PackError>;
+
+    /// Truncates the string to `len`, or pads it with null bytes.
+    fn put_len(&mut self, len: usize) -> Result<(), PackError>;
+
+    /// The string from `start` on, which is packed since the last flush.
+    fn tail(&mut self, start: usize) -> Result<&mut [u8], PackError>;
+
+    /// Resizes the bytes of `range` to `size`, moving what follows, and returns them to be written over.
+    fn put_at(&mut self, range: Range<usize>, size: usize) -> Result<&mut [u8], PackError> {
+        let len = self.len();
+        self.room(size.saturating_sub(range.len()))?;
+        self.tail(range.start)?.copy_within(range.len()..len - range.start, size);
+        self.put_len(len - range.len() + size)?;
+        Ok(&mut self.tail(range.start)?[..size])
+    }
+
+    /// Called between values, once what is packed so far won't change unless the template goes back over it.
+    fn flush(&mut self) -> Result<(), Interrupted> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Output for Packed {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> Result<(), PackError> {
+        self.try_reserve(bytes.len()).map_err(|_| PackError::OutOfMemory)?;
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn room(&mut self, size: usize) -> Result<&mut [u8], PackError> {
+        let start = self.len();
+        self.try_reserve(size).map_err(|_| PackError::OutOfMemory)?;
+        self.resize(start + size, 0);
+        Ok(&mut self[start..])
+    }
+
+    fn put_len(&mut self, len: usize) -> Result<(), PackError> {
+        self.try_reserve(len.saturating_sub(self.len())).map_err(|_| PackError::PositionOutsideOfString)?;
+        self.resize(len, 0);
+        Ok(())
+    }
+
+    fn tail(&mut self, start: usize) -> Result<&mut [u8], PackError> {
+        Ok(&mut self[start..])
+    }
+}
+
+/// The caller's buffer of [`pack_into`], `len` bytes of which are packed.
+struct Buffer<'b> {
+    bytes: &'b mut [u8],
+    len: usize,
+}
+
+impl Output for Buffer<'_> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn room(&mut self, size: usize) -> Result<&mut [u8], PackError> {
+        let (start, end) = (self.len, self.len.checked_add(size).ok_or(PackError::BufferTooSmall)?);
+        let room = self.bytes.get_mut(start..end).ok_or(PackError::BufferTooSmall)?;
+        self.len = end;
+        Ok(room)
+    }
+
+    fn put_len(&mut self, len: usize) -> Result<(), PackError> {
+        if let Some(padding) = self.bytes.get_mut(self.len..len) {
+            padding.fill(0);
+        } else if len > self.bytes.len() {
+            return Err(PackError::BufferTooSmall);
+        }
+        self.len = len;
+        Ok(())
+    }
+
+    fn tail(&mut self, start: usize) -> Result<&mut [u8], PackError> {
+        Ok(&mut self.bytes[start..self.len])
+    }
+}
+
+/// An argument as it is packed: a [`PackableArg`] into any output,
+/// or a `&dyn Packable` of [`pack_into`] packing itself into the buffer.
+trait Argument<O> {
+    fn put(self, pack_type: &PackType, output: &mut O) -> Result<(), PackError>;
+
+    /// Packs the argument into the start of `buffer` rather than into the string, see [`Packable::pack_into`].
+    fn pack_into(self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError>;
+}
+
+#[cfg(feature = "alloc")]
+impl<O: Output> Argument<O> for PackableArg<'_> {
+    fn put(self, pack_type: &PackType, output: &mut O) -> Result<(), PackError> {
+        output.put(&self.inner.pack(pack_type.clone())?)
+    }
+
+    fn pack_into(self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        let packed = self.inner.pack(pack_type.clone())?;
+        buffer.get_mut(..packed.len()).ok_or(PackError::BufferTooSmall)?.copy_from_slice(&packed);
+        Ok(packed.len())
+    }
+}
+
+impl Argument<Buffer<'_>> for &dyn Packable {
+    fn put(self, pack_type: &PackType, output: &mut Buffer<'_>) -> Result<(), PackError> {
+        let room = &mut output.bytes[output.len..];
+        let len = Packable::pack_into(self, pack_type, room)?;
+        if len > room.len() {
+            return Err(PackError::BufferTooSmall);
+        }
+        output.len += len;
+        Ok(())
+    }
+
+    fn pack_into(self, pack_type: &PackType, buffer: &mut [u8]) -> Result<usize, PackError> {
+        Packable::pack_into(self, pack_type, buffer)
+    }
+}
+
+/// Why packing stopped: an error of the template or of the arguments, or of the writer of [`pack_to`].
+enum Interrupted {
+    Pack(PackError),
+    #[cfg(feature = "std")]
+    Write(std::io::Error),
+}
+
+impl Interrupted {
+    /// The error of packing into memory, which writes nothing.
+    fn into_pack_error(self) -> PackError {
+        match self {
+            Interrupted::Pack(e) => e,
+            #[cfg(feature = "std")]
+            Interrupted::Write(_) => unreachable!("packing into memory writes nothing"),
+        }
+    }
+}
+
+impl From<PackError> for Interrupted {
+    fn from(e: PackError) -> Self {
+        Interrupted::Pack(e)
+    }
+}
+
+/// Packs the items into `packing.result`, `groups` holding them.
+fn pack_items<'t, I: Items<'t>, A: Argument<O>, T: Iterator<Item=A>, O: Output>(mut items: I, groups: &Groups, args: &mut Peekable<Enumerate<T>>, packing: &mut Packing<O>)
+    -> Result<(), Interrupted> {
+    while let Some(entry) = items.next_item() {
+        let Entry { node, index, span, tree } = entry?;
+        let (offset, taken) = (packing.result.len(), packing.taken);
+        pack_item(node, groups, args, packing).map_err(|e| packing.locate(e, tree, index, span, offset, taken))?;
+    }
+    Ok(())
+}
+
+/// Packs an item.
+fn pack_item<'t, I: Items<'t>, A: Argument<O>, T: Iterator<Item=A>, O: Output>(node: Node<I>, groups: &Groups, args: &mut Peekable<Enumerate<T>>, packing: &mut Packing<O>)
+    -> Result<(), Interrupted> {
+    let packaging = match node {
+        Node::Group(items, count) => {
+            pack_group(items, count, groups, args, packing)?;
+            return Ok(());
+        }
+        Node::LengthPrefixed(length, mut sequence) => {
+            // the length is packed once the sequence is, in the place kept for it
+            let at = packing.result.len();
+            impls::pack_scalar_with(&Scalar::Unsigned(0), &length, |size| packing.result.room(size))?;
+            let end = packing.result.len();
+            packing.held += 1;
+            let count = match sequence.next_item() {
+                Some(entry) => pack_sequence(entry?.node, groups, args, packing)?,
+                None => return Err(PackError::InvalidLengthItem.into()),
+            };
+            packing.held -= 1;
+            if packing.result.len() < end {
+                return Err(PackError::PositionOutsideOfString.into());
+            }
+            // varints are wider than the place kept for them
+            impls::pack_scalar_with(&Scalar::Unsigned(count as u128), &length, |size| packing.result.put_at(at..end, size))?;
+            return Ok(());
+        }
+        Node::Checksum => return Err(PackError::InvalidChecksum.into()),
+        Node::Format(packaging) => packaging,
+    };
+    // strings take a single argument whatever their length is, numbers take one argument per count
+    let (repeat, packaging) = match (&packaging, packaging.count()) {
+        (PackType::CharacterMode, _) => {
+            packing.characters = packing.utf8;
+            return Ok(());
+        }
+        (PackType::ByteMode, _) => {
+            packing.characters = false;
+            return Ok(());
+        }
+        (PackType::NullByte(_), Count::Exact(c)) => {
+            packing.result.put_len(packing.result.len().checked_add(c).ok_or(PackError::PositionOutsideOfString)?)?;
+            return Ok(());
+        }
+        (PackType::NullByte(_), Count::Star) => return Ok(()),
+        (p, _) if p.is_position() => {
+            let current = packing.result.len();
+            let position = match p {
+                PackType::ValuePosition(c) => {
+                    let argument = packing.next_argument(args).ok_or(PackError::RightArgumentIsMissingForTemplate)?;
+                    let mut offset = [0; 8];
+                    let offset = match argument.pack_into(&PackType::SignedQuad(Count::Exact(1), Endianness::Little), &mut offset) {
+                        Ok(8) => i64::from_le_bytes(offset),
+                        Ok(_) | Err(PackError::BufferTooSmall) => return Err(PackError::PositionOutsideOfString.into()),
+                        Err(e) => return Err(e.into()),
+                    };
+                    let from = match c {
+                        Count::Star => 0,
+                        Count::Exact(0) => current,
+                        Count::Exact(n) => groups.nth(*n),
+                    };
+                    usize::try_from(from as i128 + offset as i128).ok()
                 }
+                _ => position(p, current, groups.start, current),
+            };
+            packing.result.put_len(position.ok_or(PackError::PositionOutsideOfString)?)?;
+            return Ok(());
+        }
+        (p, _) if p.is_string() || matches!(p, PackType::Uuencoded(_)) => (Count::Exact(1), packaging),
+        (p, count) => (count, p.with_count(Count::Exact(1))),
+    };
+    let mut packed = 0;
+    while repeat != Count::Exact(packed) {
+        let argument = match (packing.next_argument(args), repeat) {
+            (Some(a), _) => a,
+            (None, Count::Star) => break,
+            (None, Count::Exact(_)) => return Err(PackError::RightArgumentIsMissingForTemplate.into()),
+        };
+        pack_argument(&packaging, argument, packing)?;
+        packing.flush()?;
+        packed += 1;
+    }
+    Ok(())
+}
+
+/// Packs a single argument, as characters for string and char formats in the character mode of UTF-8 strings.
+fn pack_argument<A: Argument<O>, O: Output>(packaging: &PackType, argument: A, packing: &mut Packing<O>) -> Result<(), PackError> {
+    let result = &mut packing.result;
+    let start = result.len();
+    match packaging {
+        PackType::StringNullPadded(_) | PackType::AsciiNullPadded(_) | PackType::AscizNullPadded(_) if packing.characters => {
+            argument.put(&PackType::StringNullPadded(Count::Star), result)?;
+            put_characters(result, start, packaging)?;
+        }
+        PackType::SignedChar(_) | PackType::UnsignedChar(_) if packing.characters => {
+            argument.put(packaging, result)?;
+            widen(result, start)?;
+        }
+        PackType::WideChar(_) if !packing.utf8 => {
+            let mut character = [0; 4];
+            let len = argument.pack_into(packaging, &mut character).map_err(|e| match e {
+                PackError::BufferTooSmall => PackError::InvalidCharacter,
+                e => e,
+            })?;
+            let c = core::str::from_utf8(&character[..len]).ok().and_then(|s| s.chars().next()).ok_or(PackError::InvalidCharacter)?;
+            result.put(&[u8::try_from(c).map_err(|_| PackError::WideCharacter)?])?;
+        }
+        _ => argument.put(packaging, result)?,
+    }
+    Ok(())
+}
+
+/// Turns the string packed from `start` on into the characters of a string format: its text when it is UTF-8,
+/// otherwise its bytes as Latin-1, truncated or padded to the count of `packaging`. Returns how many characters the string had.
+fn put_characters<O: Output>(output: &mut O, start: usize, packaging: &PackType) -> Result<usize, PackError> {
+    let string = output.tail(start)?;
+    let text = core::str::from_utf8(string).ok();
+    let characters = text.map_or(string.len(), |text| text.chars().count());
+    let kept = match (packaging, packaging.count()) {
+        (PackType::AscizNullPadded(_), Count::Exact(c)) => characters.min(c.saturating_sub(1)),
+        (_, Count::Exact(c)) => characters.min(c),
+        (_, Count::Star) => characters,
+    };
+    let padding = match (packaging, packaging.count()) {
+        (PackType::AscizNullPadded(_), Count::Star) => 1,
+        (_, Count::Exact(c)) => c - kept,
+        (_, Count::Star) => 0,
+    };
+    match text.map(|text| text.char_indices().nth(kept).map_or(text.len(), |(i, _)| i)) {
+        Some(end) => output.put_len(start + end)?,
+        None => {
+            output.put_len(start + kept)?;
+            widen(output, start)?;
+        }
+    }
+    output.room(padding)?.fill(if matches!(packaging, PackType::AsciiNullPadded(_)) { b' ' } else { 0 });
+    Ok(characters)
+}
+
+/// Turns the bytes packed from `start` on, as Latin-1, into UTF-8 in their place.
+fn widen<O: Output>(output: &mut O, start: usize) -> Result<(), PackError> {
+    let bytes = output.tail(start)?;
+    let (len, wide) = (bytes.len(), bytes.iter().filter(|b| **b >= 0x80).count());
+    output.room(wide)?;
+    let bytes = output.tail(start)?;
+    // from the end, every byte is read before its place is written
+    let mut at = len + wide;
+    for i in (0..len).rev() {
+        let mut character = [0; 2];
+        let character = char::from(bytes[i]).encode_utf8(&mut character);
+        at -= character.len();
+        bytes[at..at + character.len()].copy_from_slice(character.as_bytes());
+    }
+    Ok(())
+}
+
+/// Packs a group `count` times, or for as long as arguments are left with `*`, and returns how many times it was packed.
+fn pack_group<'t, I: Items<'t>, A: Argument<O>, T: Iterator<Item=A>, O: Output>(items: I, count: Count, groups: &Groups, args: &mut Peekable<Enumerate<T>>,
+                                                                               packing: &mut Packing<O>) -> Result<usize, Interrupted> {
+    let mut repeated = 0;
+    while count != Count::Exact(repeated) {
+        let next = match (args.peek(), count) {
+            (None, Count::Star) => break,
+            (next, _) => next.map(|(i, _)| *i),
+        };
+        let group = Groups { start: packing.result.len(), outer: Some(groups) };
+        pack_items(items.clone(), &group, args, packing)?;
+        packing.flush()?;
+        repeated += 1;
+        if count == Count::Star && args.peek().map(|(i, _)| *i) == next {
+            break; // the group takes no argument, it would repeat forever
+        }
+    }
+    Ok(repeated)
+}
+
+/// Packs the item following a `/` and returns the length to pack before it: the length of a string,
+/// or how many times a numeric format or a group was repeated, which is its count or less when arguments run out.
+fn pack_sequence<'t, I: Items<'t>, A: Argument<O>, T: Iterator<Item=A>, O: Output>(item: Node<I>, groups: &Groups, args: &mut Peekable<Enumerate<T>>,
+                                                                                  packing: &mut Packing<O>) -> Result<usize, Interrupted> {
+    match item {
+        Node::Format(PackType::NullByte(c)) => {
+            packing.result.put_len(packing.result.len().checked_add(c.or(0)).ok_or(PackError::PositionOutsideOfString)?)?;
+            Ok(c.or(0))
+        }
+        Node::Format(p) if p.is_string() => {
+            let argument = packing.next_argument(args).ok_or(PackError::RightArgumentIsMissingForTemplate)?;
+            if let Count::Exact(count) = p.count() {
+                pack_argument(&p, argument, packing)?;
+                return Ok(count);
             }
-            (None, Some(_)) => {
-                return Err(PackError::LeftArgumentIsMissingForTemplate);
+            // the raw string tells the length, then it becomes the string of the format in its place
+            let start = packing.result.len();
+            argument.put(&PackType::StringNullPadded(Count::Star), &mut packing.result)?;
+            let length = packing.result.len() - start;
+            match p {
+                // text strings count characters in character mode
+                PackType::StringNullPadded(_) | PackType::AsciiNullPadded(_) | PackType::AscizNullPadded(_) if packing.characters => {
+                    let characters = put_characters(&mut packing.result, start, &p)?;
+                    Ok(characters + usize::from(matches!(p, PackType::AscizNullPadded(_))))
+                }
+                PackType::AscizNullPadded(_) => {
+                    packing.result.put(&[0])?;
+                    Ok(length + 1)
+                }
+                PackType::StringNullPadded(_) | PackType::AsciiNullPadded(_) => Ok(length),
+                _ => {
+                    let size = impls::pack_digits(packing.result.tail(start)?, &p);
+                    packing.result.put_len(start + size)?;
+                    Ok(length)
+                }
             }
-            (Some(_), None) => {
-                return Err(PackError::RightArgumentIsMissingForTemplate);
+        }
+        Node::Group(items, Count::Star) => Ok(pack_group(items, Count::Star, groups, args, packing)?),
+        Node::Group(items, Count::Exact(count)) => {
+            // stops early once arguments run out
+            let mut repeated = 0;
+            while repeated < count && args.peek().is_some() {
+                repeated += pack_group(items.clone(), Count::Exact(1), groups, args, packing)?;
             }
-            (None, None) => {
-                return Ok(result);
+            Ok(repeated)
+        }
+        Node::Format(p) => {
+            let packaging = p.with_count(Count::Exact(1));
+            let mut repeated = 0;
+            while p.count() != Count::Exact(repeated) {
+                match packing.next_argument(args) {
+                    Some(argument) => pack_argument(&packaging, argument, packing)?,
+                    None => break,
+                }
+                repeated += 1;
             }
+            Ok(repeated)
+        }
+        Node::LengthPrefixed(..) | Node::Checksum => Err(PackError::InvalidLengthItem.into()),
+    }
+}
+
+/// The position `@`, `X`, `x!` and `X!` move to from `current`, `None` when it is before the start.
+/// `group` is the start of the innermost group, `end` the end of the data, where `@*` goes on unpack.
+fn position(pack_type: &PackType, current: usize, group: usize, end: usize) -> Option<usize> {
+    match pack_type {
+        PackType::AbsolutePosition(c) => c.or(end - group).checked_add(group),
+        PackType::BackUpByte(c) => current.checked_sub(c.or(0)),
+        PackType::BackUpByteAlign(c) => Some(current - current.checked_rem(c.or(0)).unwrap_or(0)),
+        PackType::NullByteAlign(c) => {
+            let n = c.or(0);
+            current.checked_add(current.checked_rem(n).map_or(0, |r| (n - r) % n))
         }
+        _ => Some(current),
     }
 }
 
-pub fn unpack<T>(template: &str, packed: Packed) -> Result<T, UnpackError>
-    where T: Iterator<Item=dyn Unpackable> {
-    todo!()
+/// Unpacks `packed` according to `template`, returning one [`Unpacked`] per decoded value.
+///
+/// String formats (`a`, `A`, `Z`) use the count as a length, bit and hex strings (`b`, `B`, `h`, `H`)
+/// as a number of digits, and both consume the rest of the data with `*`;
+/// numeric formats use the count as a repeat count and decode as many values as the data holds with `*`.
+/// Groups like `(nC)3` repeat their formats, `(nC)*` for as long as data is left.
+/// With `n/a*` or `C/(nC)` the decoded length becomes the count of what follows the `/`,
+/// lengths over [`DEFAULT_MAX_LENGTH`] are an error, see [`unpack_with_limit`] for another limit.
+/// `@`, `X`, `x!` and `X!` move the read position, and `.` produces it.
+/// `u` decodes uuencoded lines for as long as there are, whatever its count, the last newline can be missing.
+/// `%<bits>` before a numeric format produces the sum of its values modulo 2^bits, like `%32C*`.
+/// `U` reads UTF-8 characters, and so does `W` in UTF-8 strings, where `C0` and `U0` choose
+/// between characters and bytes for the other string and char formats, see [`PackType::CharacterMode`].
+#[cfg(feature = "alloc")]
+pub fn unpack(template: &str, packed: &[u8]) -> Result<Vec<Unpacked>, UnpackError> {
+    unpack_with_limit(template, packed, DEFAULT_MAX_LENGTH)
+}
+
+/// Default upper bound of the lengths read before a `/`.
+#[cfg(feature = "alloc")]
+pub const DEFAULT_MAX_LENGTH: usize = 1 << 24;
+
+/// Same as [`unpack()`], with lengths read before a `/` bounded by `max_length` instead of [`DEFAULT_MAX_LENGTH`].
+#[cfg(feature = "alloc")]
+pub fn unpack_with_limit(template: &str, packed: &[u8], max_length: usize) -> Result<Vec<Unpacked>, UnpackError> {
+    compiled(template).map_err(UnpackError::InvalidTemplate)?.unpack_with_limit(packed, max_length)
+}
+
+/// Same as [`unpack()`], without copying strings: `a`, `A` and `Z` borrow their bytes from `packed`.
+///
+/// ```
+/// use rust_pack::{unpack_ref, UnpackedRef};
+///
+/// let packed = b"\x00\x05hello";
+/// let values = unpack_ref("n/a*", packed).unwrap();
+/// let text: &str = values[0].clone().try_into().unwrap();
+/// assert_eq!(text, "hello");
+/// assert_eq!(values[0].clone().into_owned(), rust_pack::Unpacked::Bytes(b"hello".to_vec()));
+/// ```
+#[cfg(feature = "alloc")]
+pub fn unpack_ref<'a>(template: &str, packed: &'a [u8]) -> Result<Vec<UnpackedRef<'a>>, UnpackError> {
+    compiled(template).map_err(UnpackError::InvalidTemplate)?.unpack_ref(packed)
 }
 
-#[cfg(test)]
+/// Same as [`unpack()`], with the sizes of the native formats taken from `abi`, see [`pack_with_abi`].
+#[cfg(feature = "alloc")]
+pub fn unpack_with_abi(template: &str, packed: &[u8], abi: Abi) -> Result<Vec<Unpacked>, UnpackError> {
+    let template = Template::parse_with_abi(template, abi).map_err(UnpackError::InvalidTemplate)?;
+    unpack_template(&template, packed, DEFAULT_MAX_LENGTH)
+}
+
+#[cfg(feature = "alloc")]
+fn unpack_template(template: &Template, packed: &[u8], max_length: usize) -> Result<Vec<Unpacked>, UnpackError> {
+    Ok(unpack_borrowed(template, packed, max_length)?.into_iter().map(UnpackedRef::into_owned).collect())
+}
+
+#[cfg(feature = "alloc")]
+fn unpack_borrowed<'a>(template: &Template, packed: &'a [u8], max_length: usize) -> Result<Vec<UnpackedRef<'a>>, UnpackError> {
+    unpack_checked(template, packed, max_length, &[], false, None)
+}
+
+/// Unpacks the data, checking the values at the indexes of `expected`, and that nothing is left if `exact`.
+/// Values of groups and `/` are located at the item of the template holding them,
+/// `locations` gets the location of every value then where the template ended.
+#[cfg(feature = "alloc")]
+fn unpack_checked<'a>(template: &Template, packed: &'a [u8], max_length: usize, expected: &[(usize, Unpacked)], exact: bool,
+                      mut locations: Option<&mut Vec<Location>>) -> Result<Vec<UnpackedRef<'a>>, UnpackError> {
+    let items = template.items();
+    let mut result = Vec::with_capacity(items.len());
+    let utf8 = is_utf8(&Tree::new(template));
+    let characters = utf8 && !matches!(items.first(), Some(PackType::UnicodeChar(_)));
+    let mut cursor = Cursor { data: packed, position: 0, groups: vec![0], max_length, utf8, characters, spans: template.spans() };
+    let (mut index, mut last) = (0, 0);
+    for pack_type in items {
+        let (offset, first) = (cursor.position, result.len());
+        unpack_private(pack_type, index, &mut cursor, &mut result)?;
+        if let Some(locations) = locations.as_mut() {
+            locations.resize(result.len(), cursor.locate(index, offset));
+        }
+        for (i, value) in expected {
+            if let Some(actual) = result.get(*i).filter(|_| *i >= first) {
+                let actual = actual.clone().into_owned();
+                if actual != *value {
+                    return Err(UnpackError::Mismatch { at: cursor.locate(index, offset), expected: value.clone(), actual });
+                }
+            }
+        }
+        last = index;
+        index += nodes(pack_type);
+    }
+    if let Some((value, _)) = expected.iter().find(|(i, _)| *i >= result.len()) {
+        let at = cursor.locate(last, cursor.position);
+        return Err(UnpackError::Value { at, value: *value, cause: Box::new(UnpackError::NotEnoughData) });
+    }
+    if let Some(locations) = locations {
+        locations.push(cursor.locate(last, cursor.position));
+    }
+    if exact && cursor.position < packed.len() {
+        return Err(UnpackError::TrailingData { at: cursor.locate(last, cursor.position), remaining: packed.len() - cursor.position });
+    }
+    Ok(result)
+}
+
+/// Read position of [`unpack()`] in the whole data.
+#[cfg(feature = "alloc")]
+struct Cursor<'a, 's> {
+    data: &'a [u8],
+    position: usize,
+    /// Start of the data, then of every group being read, innermost last.
+    groups: Vec<usize>,
+    /// Upper bound of the lengths read before a `/`.
+    max_length: usize,
+    /// Whether the data is a UTF-8 string, see [`PackType::ByteMode`].
+    utf8: bool,
+    /// Whether string and char formats read characters of the UTF-8 string rather than bytes, see [`PackType::CharacterMode`].
+    characters: bool,
+    /// Where the items are in the template, see [`Template::spans`].
+    spans: &'s [Range<usize>],
+}
+
+#[cfg(feature = "alloc")]
+impl Cursor<'_, '_> {
+    fn locate(&self, item: usize, offset: usize) -> Location {
+        Location { offset, item, span: self.spans.get(item).cloned().unwrap_or_default() }
+    }
+
+    /// Locates what went wrong reading the item at `offset`, the format having been given the data from there or some of its end.
+    fn malformed(&self, e: Malformed, item: usize, offset: usize) -> UnpackError {
+        let at = self.locate(item, offset);
+        match e {
+            Malformed::Truncated { needed, available } => {
+                let left = self.data.len() - offset;
+                UnpackError::Truncated { at, needed: left - available + needed, available: left }
+            }
+            Malformed::Utf8 => UnpackError::MalformedUtf8 { at },
+            Malformed::Overflow => UnpackError::Overflow { at },
+        }
+    }
+}
+
+/// Unpacks an item, `index` being where it is in [`Template::spans`].
+#[cfg(feature = "alloc")]
+fn unpack_private<'a>(pack_type: &PackType, index: usize, cursor: &mut Cursor<'a, '_>, result: &mut Vec<UnpackedRef<'a>>) -> Result<(), UnpackError> {
+    let offset = cursor.position;
+    match pack_type {
+        PackType::Group(items, count) => {
+            // `*` repeats the group for as long as data is left
+            let mut repeated = 0;
+            while *count != Count::Exact(repeated) && !(*count == Count::Star && cursor.position == cursor.data.len()) {
+                let start = cursor.position;
+                cursor.groups.push(start);
+                let mut child = index + 1;
+                let unpacked = items.iter().try_for_each(|pack_type| {
+                    unpack_private(pack_type, child, cursor, result)?;
+                    child += nodes(pack_type);
+                    Ok(())
+                });
+                cursor.groups.pop();
+                unpacked?;
+                if *count == Count::Star && cursor.position == start {
+                    break; // the group reads no data, it would repeat forever
+                }
+                repeated += 1;
+            }
+            Ok(())
+        }
+        PackType::LengthPrefixed(length, item) => {
+            let mut lengths = Vec::with_capacity(1);
+            unpack_private(length, index + 1, cursor, &mut lengths)?;
+            let at = || cursor.locate(index + 1, offset);
+            let count = match lengths.pop().map(|length| usize::unpack(length.into_owned())) {
+                Some(Ok(count)) => count,
+                Some(Err(_)) => return Err(UnpackError::Overflow { at: at() }),
+                None => return Err(UnpackError::Truncated { at: at(), needed: 1, available: 0 }),
+            };
+            if count > cursor.max_length {
+                return Err(UnpackError::LengthOverLimit { at: at(), length: count, limit: cursor.max_length });
+            }
+            unpack_private(&item.with_count(Count::Exact(count)), index + 1 + nodes(length), cursor, result)
+        }
+        PackType::Checksum(bits, item) => {
+            let mut values = Vec::new();
+            unpack_private(item, index + 1, cursor, &mut values)?;
+            result.push(impls::checksum(*bits, item, &values).into());
+            Ok(())
+        }
+        PackType::ValuePosition(c) => {
+            let from = match c {
+                Count::Star => 0,
+                Count::Exact(0) => cursor.position,
+                Count::Exact(n) => cursor.groups.len().checked_sub(*n).map_or(0, |i| cursor.groups[i]),
+            };
+            result.push(match cursor.position.checked_sub(from) {
+                Some(offset) => UnpackedRef::Unsigned(offset as u128),
+                None => UnpackedRef::Signed(-((from - cursor.position) as i128)), // backed up before the group with `X`
+            });
+            Ok(())
+        }
+        PackType::CharacterMode => {
+            cursor.characters = cursor.utf8;
+            Ok(())
+        }
+        PackType::ByteMode => {
+            cursor.characters = false;
+            Ok(())
+        }
+        PackType::WideChar(c) if !cursor.utf8 => unpack_private(&PackType::UnsignedChar(*c), index, cursor, result),
+        PackType::WideChar(c) => unpack_private(&PackType::UnicodeChar(*c), index, cursor, result),
+        PackType::SignedChar(c) | PackType::UnsignedChar(c) if cursor.characters => {
+            // wraps characters above 255 like Perl
+            let mut characters = Vec::new();
+            unpack_private(&PackType::UnicodeChar(*c), index, cursor, &mut characters)?;
+            result.extend(characters.into_iter().map(|c| match c {
+                UnpackedRef::Unsigned(c) => impls::decode_number(pack_type, &[c as u8]).into(),
+                c => c,
+            }));
+            Ok(())
+        }
+        PackType::StringNullPadded(c) | PackType::AsciiNullPadded(c) | PackType::AscizNullPadded(c) if cursor.characters => {
+            // the length counts characters, text is cut and trimmed the same way as bytes
+            let data = &cursor.data[cursor.position..];
+            let field = match c {
+                Count::Star => pack_type.clone(),
+                Count::Exact(n) => pack_type.with_count(Count::Exact(impls::utf8_length(data, *n).map_err(|e| cursor.malformed(e, index, offset))?)),
+            };
+            let rest = unpack_format(&field, data, result).map_err(|e| cursor.malformed(e, index, offset))?;
+            if let Some(UnpackedRef::Bytes(text)) = result.last() {
+                core::str::from_utf8(text).map_err(|_| UnpackError::MalformedUtf8 { at: cursor.locate(index, offset) })?;
+            }
+            cursor.position = cursor.data.len() - rest.len();
+            Ok(())
+        }
+        p if p.is_position() => {
+            match position(p, cursor.position, *cursor.groups.last().unwrap(), cursor.data.len()) {
+                Some(position) if position <= cursor.data.len() => cursor.position = position,
+                _ => return Err(UnpackError::PositionOutsideOfData { at: cursor.locate(index, offset) }),
+            }
+            Ok(())
+        }
+        _ => {
+            let rest = unpack_format(pack_type, &cursor.data[cursor.position..], result).map_err(|e| cursor.malformed(e, index, offset))?;
+            cursor.position = cursor.data.len() - rest.len();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn unpack_format<'a>(pack_type: &PackType, data: &'a [u8], result: &mut Vec<UnpackedRef<'a>>) -> Result<&'a [u8], Malformed> {
+    match pack_type {
+        PackType::StringNullPadded(c) | PackType::AsciiNullPadded(c) | PackType::AscizNullPadded(c) => {
+            let (field, rest) = take(data, c.or(data.len()))?;
+            let value = match pack_type {
+                PackType::AsciiNullPadded(_) => {
+                    let end = field.iter().rposition(|b| *b != b' ' && *b != 0).map_or(0, |p| p + 1);
+                    &field[..end]
+                }
+                PackType::AscizNullPadded(_) => {
+                    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+                    if *c == Count::Star && end < field.len() {
+                        // with `*` only the string and its terminator are consumed
+                        result.push(UnpackedRef::Bytes(Cow::Borrowed(&field[..end])));
+                        return Ok(&data[end + 1..]);
+                    }
+                    &field[..end]
+                }
+                _ => field,
+            };
+            result.push(UnpackedRef::Bytes(Cow::Borrowed(value)));
+            Ok(rest)
+        }
+        PackType::BitStringAscending(c) | PackType::BitStringDescending(c) => {
+            let bits = c.or(data.len() * 8);
+            let (field, rest) = take(data, bits.div_ceil(8))?;
+            let ascending = matches!(pack_type, PackType::BitStringAscending(_));
+            let digits = (0..bits).map(|i| {
+                let shift = if ascending { i % 8 } else { 7 - i % 8 };
+                b'0' + (field[i / 8] >> shift & 1)
+            });
+            result.push(UnpackedRef::Bytes(Cow::Owned(digits.collect())));
+            Ok(rest)
+        }
+        PackType::HexStringLowFirst(c) | PackType::HexStringHighFirst(c) => {
+            let nybbles = c.or(data.len() * 2);
+            let (field, rest) = take(data, nybbles.div_ceil(2))?;
+            let low_first = matches!(pack_type, PackType::HexStringLowFirst(_));
+            let digits = (0..nybbles).map(|i| {
+                let shift = if low_first == (i % 2 == 0) { 0 } else { 4 };
+                b"0123456789abcdef"[(field[i / 2] >> shift & 0xf) as usize]
+            });
+            result.push(UnpackedRef::Bytes(Cow::Owned(digits.collect())));
+            Ok(rest)
+        }
+        PackType::Uuencoded(_) => {
+            let (value, size) = impls::uudecode(data);
+            result.push(UnpackedRef::Bytes(Cow::Owned(value)));
+            Ok(&data[size..])
+        }
+        PackType::NullByte(c) => Ok(take(data, c.or(0))?.1),
+        PackType::UnicodeChar(c) => {
+            let mut data = data;
+            for _ in 0..c.or(usize::MAX) {
+                if *c == Count::Star && data.is_empty() {
+                    break;
+                }
+                let (character, size) = impls::decode_utf8(data)?;
+                result.push(UnpackedRef::Unsigned(character as u128));
+                data = &data[size..];
+            }
+            Ok(data)
+        }
+        p if p.is_varint() => {
+            let mut data = data;
+            for _ in 0..p.count().or(usize::MAX) {
+                if p.count() == Count::Star && data.is_empty() {
+                    break;
+                }
+                let (value, size) = impls::decode_varint(p, data)?;
+                result.push(value.into());
+                data = &data[size..];
+            }
+            Ok(data)
+        }
+        _ => {
+            // every other format is numeric and has a size
+            let size = pack_type.size().unwrap();
+            let mut data = data;
+            for _ in 0..pack_type.count().or(data.len() / size) {
+                let (field, rest) = take(data, size)?;
+                result.push(impls::decode_number(pack_type, field).into());
+                data = rest;
+            }
+            Ok(data)
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn take(data: &[u8], len: usize) -> Result<(&[u8], &[u8]), Malformed> {
+    if data.len() < len {
+        return Err(Malformed::Truncated { needed: len, available: data.len() });
+    }
+    Ok(data.split_at(len))
+}
+
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
     use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        /// Number of allocations of the thread so far, see [`Counting`].
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// The system allocator counting allocations, to check that [`pack_into`] makes none.
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    fn parse_template(template: &str) -> Result<Vec<PackType>, PackError> {
+        Template::parse(template).map(|t| t.items().to_vec()).map_err(|e| e.error)
+    }
+
+    struct TestArg;
+
+    impl Packable for TestArg {
+        fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
+            match pack_type {
+                PackType::StringNullPadded(Count::Exact(10)) => Ok(vec![0, 10]),
+                PackType::UnsignedShort(Count::Exact(1), _) => Ok(vec![33, 3]),
+                PackType::SignedShort(Count::Exact(1), _) => Ok(vec![44, 44]),
+                _ => Err(PackError::InvalidFormatCharacter)
+            }
+        }
+    }
 
     #[test]
     fn test_pack() {
-        impl Packable for u16 {
+        let pack = pack("a[10]S3s", [TestArg, TestArg, TestArg, TestArg, TestArg].map(|f| PackableArg { inner: Box::new(f) }).into_iter());
+        assert!(pack.is_ok());
+        assert!(pack.unwrap().eq(&[0, 10, 33, 3, 33, 3, 33, 3, 44, 44u8]));
+    }
+
+    #[test]
+    fn test_unpack() {
+        let data = [1, 2, 3, 4, 0xff, 0xfe, b'h', b'i', b' ', 0, b'x', b'y', 0, 0];
+        let values = unpack("nvcCA4Z*", &data).unwrap();
+        assert_eq!(values, vec![
+            Unpacked::Unsigned(0x0102),
+            Unpacked::Unsigned(0x0403),
+            Unpacked::Signed(-1),
+            Unpacked::Unsigned(0xfe),
+            Unpacked::Bytes(b"hi".to_vec()),
+            Unpacked::Bytes(b"xy".to_vec()),
+        ]);
+        assert_eq!(unpack("N2x", &[0, 0, 0, 1, 0, 0, 0, 2, 0]).unwrap(), vec![Unpacked::Unsigned(1), Unpacked::Unsigned(2)]);
+        assert!(matches!(unpack("N", &[0, 0, 1]), Err(UnpackError::Truncated { needed: 4, available: 3, .. })));
+        assert!(matches!(unpack("y", &[]), Err(UnpackError::InvalidTemplate(e)) if e.root_cause() == &PackError::InvalidFormatCharacter));
+    }
+
+    #[test]
+    fn test_macros() {
+        let argument = String::from("ls");
+        let packed = pack!("VcZ*", 10u16, 'l', argument).unwrap();
+        assert_eq!(packed, vec![10, 0, 0, 0, b'l', b'l', b's', 0]);
+        let (size, command, argument) = unpack!("VcZ*", packed).unwrap();
+        assert_eq!((size, command, argument.as_str()), (10u32, 108i8, "ls"));
+        let pairs: (u16, u16, Vec<u8>) = unpack!("n2a2", [0, 1, 0, 2, b'o', b'k']).unwrap();
+        assert_eq!(pairs, (1, 2, b"ok".to_vec()));
+        assert_eq!(unpack!("x2C", &[9, 9, 7][..]).unwrap(), 7);
+        let template = "C";
+        assert_eq!(pack!(template, 1).unwrap(), vec![1]);
+        let (f, d): (f32, f64) = unpack!("f<d>", pack!("f<d>", 0.5, 2).unwrap()).unwrap();
+        assert_eq!((f, d), (0.5, 2.0));
+        assert_eq!(unpack!("u", pack!("u", "Cat").unwrap()).unwrap(), b"Cat");
+        assert_eq!(pack!("n # the length\n a*", 2, "ok").unwrap(), b"\0\x02ok");
+        assert_eq!(pack!("n\x20C", 1, 2).unwrap(), [0, 1, 2]);
+        assert_eq!(pack!("n\u{20}C\u{0_9}a\x2a", 1, 2, "\"\\").unwrap(), b"\0\x01\x02\"\\");
+        assert_eq!(unpack!("n\x20C", [0, 1, 2]).unwrap(), (1, 2));
+        assert_eq!(unpack!("x[N] C[2]", [0, 0, 0, 0, 1, 2]).unwrap(), (1, 2));
+        let (s, n): (i16, i32) = unpack!("s>N!", [0xff, 0xfe, 0xff, 0xff, 0xff, 0xfd]).unwrap();
+        assert_eq!((s, n), (-2, -3));
+        let (w, e, z) = unpack!("wez", pack!("wez", u128::MAX, 300, -2).unwrap()).unwrap();
+        assert_eq!((w, e, z), (u128::MAX, 300u128, -2i128));
+    }
+
+    #[test]
+    fn test_derive() {
+        #[derive(Pack, Unpack, Debug, PartialEq)]
+        struct Packet {
+            #[pack("v")]
+            size: u16,
+            /// the command
+            #[pack("c")]
+            pub command: u8,
+            #[pack("Z*")]
+            argument: String,
+        }
+
+        #[derive(Pack, Unpack, Debug, PartialEq)]
+        struct Pair(#[pack("n")] u16, #[pack("A\x33")] String);
+
+        #[derive(Pack, Unpack, Debug, PartialEq)]
+        struct Commented {
+            #[pack("n # length")]
+            length: u16,
+            #[pack("C")]
+            kind: u8,
+        }
+
+        let p = Packet { size: 3, command: 1, argument: "ls".to_string() };
+        let packed = Pack::pack(&p).unwrap();
+        assert_eq!(packed, b"\x03\x00\x01ls\x00");
+        assert_eq!(<Packet as Unpack>::unpack(&packed).unwrap(), p);
+        assert_eq!(Pair::TEMPLATE, "n\nA3");
+        assert_eq!(<Pair as Unpack>::unpack(b"\x00\x07ab ").unwrap(), Pair(7, "ab".to_string()));
+        let c = Commented { length: 2, kind: 9 };
+        assert_eq!(Pack::pack(&c).unwrap(), b"\x00\x02\x09");
+        assert_eq!(<Commented as Unpack>::unpack(b"\x00\x02\x09").unwrap(), c);
+    }
+
+    #[test]
+    fn test_repeat_counts() {
+        let packed = pack!("a*C*", "abc", 1, 2, 3).unwrap();
+        assert_eq!(packed, b"abc\x01\x02\x03");
+        assert_eq!(pack!("n2 x2 C*", 1, 2).unwrap(), vec![0, 1, 0, 2, 0, 0]);
+        assert_eq!(pack!("n2 x2 C*", 1, 2, 3).unwrap(), vec![0, 1, 0, 2, 0, 0, 3]);
+        assert_eq!(pack!("a Z A3", "xyz", "xyz", "xyz").unwrap(), b"x\0xyz");
+        assert_eq!(pack("N3", [1, 2].map(PackableArg::from).into_iter()).unwrap_err().root_cause(), &PackError::RightArgumentIsMissingForTemplate);
+        assert!(matches!(pack("N", [1, 2].map(PackableArg::from).into_iter()), Err(PackError::LeftArgumentIsMissingForTemplate)));
+        assert_eq!(unpack("n*", &[0, 1, 0, 2, 9]).unwrap(), vec![Unpacked::Unsigned(1), Unpacked::Unsigned(2)]);
+        assert_eq!(unpack("a2 a*", b"abcd").unwrap(), vec![Unpacked::Bytes(b"ab".to_vec()), Unpacked::Bytes(b"cd".to_vec())]);
+        assert_eq!(unpack("Z", b"ab").unwrap(), vec![Unpacked::Bytes(b"a".to_vec())]);
+    }
+
+    #[test]
+    fn test_endianness() {
+        let packed = pack!("s<S>l<L>q>Q<n!N!v!V!", -2, 2, -3, 3, -4, 4, -5, -6, -7, -8).unwrap();
+        assert_eq!(packed, b"\xfe\xff\x00\x02\xfd\xff\xff\xff\x00\x00\x00\x03\
+            \xff\xff\xff\xff\xff\xff\xff\xfc\x04\x00\x00\x00\x00\x00\x00\x00\
+            \xff\xfb\xff\xff\xff\xfa\xf9\xff\xf8\xff\xff\xff");
+        let values = unpack("s<S>l<L>q>Q<n!N!v!V!", &packed).unwrap();
+        assert_eq!(values, [-2, 2, -3, 3, -4, 4, -5, -6, -7, -8].map(|v| if v < 0 { Unpacked::Signed(v) } else { Unpacked::Unsigned(v as u128) }));
+        assert_eq!(PackType::try_from("q!<2"), Err(PackError::InvalidFormatModifier));
+        assert_eq!(PackType::try_from("l!<2"), Ok(PackType::NativeSignedLong(Count::Exact(2), Endianness::Little)));
+        assert_eq!(PackType::try_from("n<"), Err(PackError::InvalidFormatModifier));
+        assert_eq!(PackType::try_from("q<>"), Err(PackError::InvalidFormatModifier));
+        assert_eq!(PackType::try_from("s>*"), Ok(PackType::SignedShort(Count::Star, Endianness::Big)));
+    }
+
+    #[test]
+    fn test_groups() {
+        let packed = pack!("(nC)2", 1, 2, 3, 4).unwrap();
+        assert_eq!(packed, vec![0, 1, 2, 0, 3, 4]);
+        assert_eq!(unpack!("(nC)2", &packed).unwrap(), (1, 2, 3, 4));
+        assert_eq!(unpack("(nC)*", &packed).unwrap(), [1, 2, 3, 4].map(Unpacked::Unsigned));
+        assert_eq!(pack("(n)*", [1, 2, 3].map(PackableArg::from).into_iter()).unwrap(), vec![0, 1, 0, 2, 0, 3]);
+        assert_eq!(pack!("C((C)2x)2", 9, 1, 2, 3, 4).unwrap(), vec![9, 1, 2, 0, 3, 4, 0]);
+        assert_eq!(pack!("(sl)>(S(L)2)<", -2, 1, 3, 4, 5).unwrap(), b"\xff\xfe\x00\x00\x00\x01\x03\x00\x04\x00\x00\x00\x05\x00\x00\x00");
+        assert_eq!(unpack("(s)<", &[1, 0]).unwrap(), vec![Unpacked::Signed(1)]);
+        assert_eq!(parse_template("(s>)<"), Err(PackError::InvalidFormatModifier));
+        assert_eq!(parse_template("(s<)<"), Ok(vec![PackType::Group(vec![PackType::SignedShort(Count::Exact(1), Endianness::Little)], Count::Exact(1))]));
+        assert_eq!(parse_template("(n)!"), Err(PackError::InvalidFormatModifier));
+        assert_eq!(parse_template("(nN"), Err(PackError::UnbalancedParentheses));
+        assert_eq!(parse_template("nN)"), Err(PackError::UnbalancedParentheses));
+        assert!(matches!(pack("(x)*", [1].map(PackableArg::from).into_iter()), Err(PackError::LeftArgumentIsMissingForTemplate)));
+        let at = Location { offset: 3, item: 1, span: 1..2 };
+        assert_eq!(unpack("(nC)2", &[0, 1, 2]), Err(UnpackError::Truncated { at, needed: 2, available: 0 }));
+    }
+
+    #[test]
+    fn test_length_prefixed() {
+        let packed = pack!("n/a* C/Z*", "hello", "hi").unwrap();
+        assert_eq!(packed, b"\x00\x05hello\x03hi\x00");
+        let (hello, hi) = unpack!("n/a* C/Z*", &packed).unwrap();
+        assert_eq!((hello, hi.as_str()), (b"hello".to_vec(), "hi"));
+        assert_eq!(pack!("C/a3", "hello").unwrap(), b"\x03hel");
+        assert_eq!(pack!("a3/A A*", " Bond", "J").unwrap(), b"5\x00\x00 BondJ");
+        assert_eq!(unpack!("a3/A A*", b"007 Bond  J ").unwrap(), (" Bond".to_string(), "J".to_string()));
+        assert_eq!(pack!("C/n", 1, 2).unwrap(), vec![2, 0, 1, 0, 2]);
+        assert_eq!(pack!("C/n2 C", 1, 2, 3).unwrap(), vec![2, 0, 1, 0, 2, 3]);
+        assert_eq!(pack!("C/(nC)", 1, 2, 3, 4).unwrap(), vec![2, 0, 1, 2, 0, 3, 4]);
+        assert_eq!(unpack("C/(nC) C", &[2, 0, 1, 2, 0, 3, 4, 5]).unwrap(), [1, 2, 3, 4, 5].map(Unpacked::Unsigned));
+        assert_eq!(unpack("(n/a*)2", b"\x00\x01a\x00\x02bc").unwrap(), vec![Unpacked::Bytes(b"a".to_vec()), Unpacked::Bytes(b"bc".to_vec())]);
+        assert!(matches!(unpack_with_limit("N/a*", b"\x00\x01\x00\x00", 0xffff), Err(UnpackError::LengthOverLimit { .. })));
+        assert!(matches!(unpack("N/C", b"\xff\xff\xff\xff"), Err(UnpackError::LengthOverLimit { .. })));
+        let at = Location { offset: 2, item: 2, span: 2..4 };
+        assert_eq!(unpack("n/a*", b"\x00\x05abc"), Err(UnpackError::Truncated { at, needed: 5, available: 3 }));
+        assert_eq!(parse_template("n2/a*"), Err(PackError::InvalidLengthItem));
+        assert_eq!(parse_template("a*/a*"), Err(PackError::InvalidLengthItem));
+        assert_eq!(parse_template("(n)/a*"), Err(PackError::InvalidLengthItem));
+        assert_eq!(parse_template("n/"), Err(PackError::EmptyFormatCharacter));
+        assert_eq!(parse_template("n/C/a"), Err(PackError::EmptyFormatCharacter));
+    }
+
+    #[test]
+    fn test_positions() {
+        assert_eq!(pack!("a* @4 C", "ab", 1).unwrap(), vec![b'a', b'b', 0, 0, 1]);
+        assert_eq!(pack!("a* @1 C", "abc", 1).unwrap(), vec![b'a', 1]);
+        assert_eq!(pack!("C (C @2 C)2", 9, 1, 2, 3, 4).unwrap(), vec![9, 1, 0, 2, 3, 0, 4]);
+        assert_eq!(pack!("a* X2 C", "abc", 1).unwrap(), vec![b'a', 1]);
+        assert_eq!(pack!("C x!4 C X!2 C", 1, 2, 3).unwrap(), vec![1, 0, 0, 0, 3]);
+        assert_eq!(pack!("a* .", "abcd", 2).unwrap(), b"ab");
+        assert_eq!(pack!("C (a* .0)", 9, "ab", 1).unwrap(), b"\x09ab\x00");
+        assert_eq!(pack!("C (a* .)", 9, "ab", 1).unwrap(), b"\x09a");
+        assert_eq!(pack!("C (a* .*)", 9, "ab", 1).unwrap(), b"\x09");
+        assert_eq!(pack!("C X2", 1).unwrap_err().root_cause(), &PackError::PositionOutsideOfString);
+        assert_eq!(pack!("C .", 1, -2).unwrap_err().root_cause(), &PackError::PositionOutsideOfString);
+        assert_eq!(pack!("C (C @18446744073709551615)", 1, 2).unwrap_err().root_cause(), &PackError::PositionOutsideOfString);
+        assert_eq!(pack!("C x18446744073709551615", 1).unwrap_err().root_cause(), &PackError::PositionOutsideOfString);
+        assert_eq!(pack!("C x18446744073709551614", 1).unwrap_err().root_cause(), &PackError::PositionOutsideOfString);
+        assert_eq!(pack!("C n/x18446744073709551615", 1).unwrap_err().root_cause(), &PackError::PositionOutsideOfString);
+        let args: [&dyn Packable; 1] = [&1];
+        assert_eq!(pack_into("C x18446744073709551615", args.into_iter(), &mut [0; 4]), Err(PackError::PositionOutsideOfString));
+
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(unpack("C @4 C X2 C x!4 C", &data).unwrap(), [1, 5, 4, 5].map(Unpacked::Unsigned));
+        assert_eq!(unpack("C2 (C @2 C)", &data).unwrap(), [1, 2, 3, 5].map(Unpacked::Unsigned));
+        assert_eq!(unpack("C3 X!2 C .", &data).unwrap(), [1, 2, 3, 3, 3].map(Unpacked::Unsigned));
+        assert_eq!(unpack("C (C2 . .0 .2 .*)", &data).unwrap(), [1, 2, 3, 2, 0, 3, 3].map(Unpacked::Unsigned));
+        assert_eq!(unpack("C (X .)", &data).unwrap(), vec![Unpacked::Unsigned(1), Unpacked::Signed(-1)]);
+        assert!(matches!(unpack("@9", &data), Err(UnpackError::PositionOutsideOfData { .. })));
+        assert!(matches!(unpack("C X2", &data), Err(UnpackError::PositionOutsideOfData { .. })));
+        assert!(matches!(unpack("C x!16", &data), Err(UnpackError::PositionOutsideOfData { .. })));
+        assert!(matches!(unpack("C (C @18446744073709551615)", &data), Err(UnpackError::PositionOutsideOfData { .. })));
+        assert_eq!(parse_template("n/@"), Err(PackError::InvalidLengthItem));
+        assert_eq!(parse_template("X!8"), Ok(vec![PackType::BackUpByteAlign(Count::Exact(8))]));
+    }
+
+    #[test]
+    fn test_checksums() {
+        let data = [0xff, 0xff, 0xff, 0xff, 2, 0x80];
+        assert_eq!(unpack("%32C*", &data).unwrap(), vec![Unpacked::Unsigned(0x47e)]);
+        assert_eq!(unpack("%8C*", &data).unwrap(), vec![Unpacked::Unsigned(0x7e)]);
+        assert_eq!(unpack("%C*", &data).unwrap(), vec![Unpacked::Unsigned(0x47e)]);
+        assert_eq!(unpack("%32c*", &data).unwrap(), vec![Unpacked::Unsigned(0xffffff7e)]);
+        assert_eq!(unpack("%64c*", &data).unwrap(), vec![Unpacked::Unsigned(0xffffffffffffff7e)]);
+        assert_eq!(unpack("%32b*", &data).unwrap(), vec![Unpacked::Unsigned(34)]);
+        assert_eq!(unpack("%16n2 n", &data).unwrap(), [0xfffe, 0x0280].map(Unpacked::Unsigned));
+        assert_eq!(unpack("%72C*", &data).unwrap(), vec![Unpacked::Float(1150.0)]);
+        assert_eq!(unpack!("%8C2 %72C2", &data).unwrap(), (0xfe, 510.0));
+        assert_eq!(unpack("%3d<", &10.5f64.to_le_bytes()).unwrap(), vec![Unpacked::Float(2.5)]);
+        assert_eq!(unpack("%3d<", &(-1.5f64).to_le_bytes()).unwrap(), vec![Unpacked::Float(6.5)]);
+        assert_eq!(unpack("%32W*", &[1, 2, 0xff]).unwrap(), vec![Unpacked::Unsigned(0x102)]);
+        assert_eq!(unpack("U0 %32W*", "aé".as_bytes()).unwrap(), vec![Unpacked::Unsigned(0x14a)]);
+        assert_eq!(unpack("%8U*", "aé€".as_bytes()).unwrap(), vec![Unpacked::Unsigned(0xf6)]);
+        assert_eq!(unpack!("%32W*", &[1, 2, 0xff]).unwrap(), 0x102);
+        assert!(matches!(unpack("%32a*", &data), Err(UnpackError::InvalidTemplate(e)) if e.root_cause() == &PackError::InvalidChecksum));
+        assert!(matches!(unpack("%32(C)", &data), Err(UnpackError::InvalidTemplate(e)) if e.root_cause() == &PackError::InvalidChecksum));
+        assert_eq!(pack("%32C", [1].map(PackableArg::from).into_iter()).unwrap_err().root_cause(), &PackError::InvalidChecksum);
+        assert_eq!(parse_template("%32n/a"), Err(PackError::InvalidLengthItem));
+        assert_eq!(parse_template("%"), Err(PackError::EmptyFormatCharacter));
+    }
+
+    #[test]
+    fn test_unicode() {
+        assert_eq!(pack!("U", '☺').unwrap(), "☺".as_bytes());
+        assert_eq!(unpack!("U", "☺".as_bytes()).unwrap(), '☺');
+        let packed = pack!("U2W", 'é', 0x263a, 'x').unwrap();
+        assert_eq!(packed, "é☺x".as_bytes());
+        assert_eq!(unpack!("U2W", packed).unwrap(), ('é', '☺', 'x'));
+        assert_eq!(unpack("U*", "añ☺".as_bytes()).unwrap(), ['a', 'ñ', '☺'].map(|c| Unpacked::Unsigned(c as u128)));
+        // a byte string, where `W` is a byte
+        assert_eq!(pack!("CWU", 1, 'é', 'é').unwrap(), [1, 0xe9, 0xc3, 0xa9]);
+        assert_eq!(unpack!("CWU", [1, 0xe9, 0xc3, 0xa9]).unwrap(), (1, 'é', 'é'));
+        assert_eq!(pack!("C0W", '☺').unwrap_err().root_cause(), &PackError::WideCharacter);
+        // character mode counts characters of strings, and packs `C` as a character
+        let packed = pack!("U0 C0 A3 C Z*", "héllo", 233, "☺").unwrap();
+        assert_eq!(packed, "hélé☺\0".as_bytes());
+        let (text, c, smiley): (String, u8, String) = unpack!("U0 C0 A3 C Z*", packed).unwrap();
+        assert_eq!((text.as_str(), c, smiley.as_str()), ("hél", 233, "☺"));
+        assert_eq!(pack!("U0 C0 C/a*", "héllo").unwrap(), [&[5], "héllo".as_bytes()].concat());
+        assert_eq!(unpack!("U0 C0 C/A*", [&[5], "héllo!".as_bytes()].concat()).unwrap(), "héllo");
+        assert_eq!(unpack!("U0 C0 C", "☺".as_bytes()).unwrap(), 0x3a);
+        // byte mode counts bytes
+        assert_eq!(pack!("U0 A3 C", "héllo", 233).unwrap(), [b'h', 0xc3, 0xa9, 233]);
+        assert_eq!(unpack!("U a2 C0 a2", "☺é☺x".as_bytes()).unwrap(), ('☺', vec![0xc3, 0xa9], "☺x".as_bytes().to_vec()));
+        assert!(matches!(unpack("U", &[0xff]), Err(UnpackError::MalformedUtf8 { .. })));
+        assert!(matches!(unpack("U", &[0xe2, 0x98]), Err(UnpackError::Truncated { needed: 3, available: 2, .. })));
+        let at = Location { offset: 0, item: 2, span: 6..8 };
+        assert_eq!(unpack("U0 C0 a2", &[b'a', 0xff]), Err(UnpackError::MalformedUtf8 { at }));
+        assert_eq!(pack!("U", 0x110000).unwrap_err().root_cause(), &PackError::InvalidCharacter);
+        assert_eq!(parse_template("C0/a"), Err(PackError::InvalidLengthItem));
+        assert_eq!(parse_template("n/U0"), Err(PackError::InvalidLengthItem));
+    }
+
+    #[test]
+    fn test_native_sizes() {
+        use core::ffi::{c_int, c_long, c_short};
+        let packed = pack!("i I! s! l! L! j J", -1, 2, 3, 4, 5, 6, 7).unwrap();
+        assert_eq!(packed.len(), 2 * size_of::<c_int>() + size_of::<c_short>() + 2 * size_of::<c_long>() + 2 * size_of::<usize>());
+        let (i, big, j): (c_int, c_long, usize) = unpack!("i l!> J<", pack!("i l!> J<", -1, 2, 3).unwrap()).unwrap();
+        assert_eq!((i, big, j), (-1, 2, 3));
+        let args = || [-2, 3, 4, 5, 6].map(PackableArg::from).into_iter();
+        assert_eq!(pack_with_abi("l!< L!< j< J< s!<", args(), Abi::ILP32).unwrap().len(), 18);
+        assert_eq!(pack_with_abi("l!< L!< j< J< s!<", args(), Abi::LP64).unwrap().len(), 34);
+        assert_eq!(pack_with_abi("(l!J)>", [1, 2].map(PackableArg::from).into_iter(), Abi::LLP64).unwrap(), [0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2]);
+        assert_eq!(unpack_with_abi("l!<", &[0xfe, 0xff, 0xff, 0xff], Abi::ILP32).unwrap(), vec![Unpacked::Signed(-2)]);
+        // `[template]` counts follow the ABI too
+        assert_eq!(pack_with_abi("x[l!] C", [1].map(PackableArg::from).into_iter(), Abi::ILP32).unwrap(), [0, 0, 0, 0, 1]);
+        assert_eq!(pack_with_abi("x[l! J] C", [1].map(PackableArg::from).into_iter(), Abi::LP64).unwrap().len(), 17);
+        assert_eq!(unpack_with_abi("x[l!] C", &[0, 0, 0, 0, 7, 8, 9, 10, 11], Abi::ILP32).unwrap(), vec![Unpacked::Unsigned(7)]);
+        assert_eq!(unpack_with_abi("x[l!] C", &[0, 0, 0, 0, 7, 8, 9, 10, 11], Abi::LP64).unwrap(), vec![Unpacked::Unsigned(11)]);
+        assert!(matches!(unpack_with_abi("l!<", &[0xfe, 0xff, 0xff, 0xff], Abi::LP64), Err(UnpackError::Truncated { needed: 8, .. })));
+        let abi = Abi { int: 3, ..Abi::LP64 };
+        assert_eq!(pack_with_abi("C/i", [1].map(PackableArg::from).into_iter(), abi), Err(PackError::UnsupportedNativeSize));
+        assert_eq!(Abi::NATIVE.long, size_of::<c_long>());
+        assert_eq!(parse_template("j!"), Err(PackError::InvalidFormatModifier));
+    }
+
+    #[test]
+    fn test_pack_into() {
+        let args: [&dyn Packable; 2] = [&"abc", &7];
+        let mut buffer = [0xff; 8];
+        assert_eq!(pack_into("n/a* C", args.into_iter(), &mut buffer), Ok(6));
+        assert_eq!(buffer[..6], pack!("n/a* C", "abc", 7).unwrap());
+        assert_eq!(pack_into("n/a* C", args.into_iter(), &mut buffer[..5]), Err(PackError::BufferTooSmall));
+        // the length grows from one byte to two once the string is packed
+        let long: [&dyn Packable; 1] = [&[b'a'; 200]];
+        assert_eq!(pack_into("w/a*", long.into_iter(), &mut [0; 202]), Ok(202));
+        assert_eq!(pack_into("w/a*", long.into_iter(), &mut [0; 201]), Err(PackError::BufferTooSmall));
+        let mut buffer = [0xff; 6];
+        assert_eq!(Template::compile("a3 X2 C @6").unwrap().pack_into(args.into_iter(), &mut buffer), Ok(6));
+        assert_eq!(buffer, [b'a', 7, 0, 0, 0, 0]);
+        assert_eq!(pack_into("a3 X2 C @7", args.into_iter(), &mut buffer), Err(PackError::BufferTooSmall));
+        assert_eq!(pack_into("a3 X2 y", args.into_iter(), &mut buffer), Err(PackError::InvalidFormatCharacter));
+        assert_eq!(pack_into(" ", args.into_iter(), &mut buffer), Err(PackError::EmptyTemplate));
+        assert_eq!(pack_into("a3", args.into_iter(), &mut buffer), Err(PackError::LeftArgumentIsMissingForTemplate));
+
+        // every format packs the same as into a `Vec`, without allocating
+        macro_rules! packs_into {
+            ($template:literal $(, $arg:expr)*) => {{
+                let packed = pack!($template $(, $arg)*).unwrap();
+                let args: &[&dyn Packable] = &[$(&$arg),*];
+                let mut buffer = [0; 64];
+                let allocations = ALLOCATIONS.with(Cell::get);
+                let len = pack_into($template, args.iter().copied(), &mut buffer);
+                assert_eq!(ALLOCATIONS.with(Cell::get), allocations);
+                assert_eq!(&buffer[..len.unwrap()], packed);
+            }};
+        }
+        packs_into!("c f< Z* W w E b* H4 (a*)", -2, 1.5, "Zoé", 'é', 300u16, -65, "10110", "beef", [0xe9, 0xff]);
+        packs_into!("U n/a* C0 a3 W C x![8] (s<)2 .", 'é', "é", "Zoé", 'é', 233, -1, 2, 3);
+        packs_into!("a3 n/Z* A4 C U0 C", [0xe9, 0x41, 0x42], "Zoé", [0xff], 255, 255);
+        packs_into!("N/Z* d> A5 n/h* v/b* x2 X x!4 N/a2 (C .2) w/(z)*", "abc", 2.5, "xy", "a1f", "1011", "hello", 7, 30, -1, 5, -300);
+        let args: [&dyn Packable; 2] = [&"abc", &7];
+        let allocations = ALLOCATIONS.with(Cell::get);
+        assert_eq!(pack_into("n/a* C", args.into_iter(), &mut [0; 5]), Err(PackError::BufferTooSmall));
+        assert_eq!(pack_into("n/a* C y", args.into_iter(), &mut [0; 8]), Err(PackError::InvalidFormatCharacter));
+        assert_eq!(ALLOCATIONS.with(Cell::get), allocations);
+    }
+
+    #[test]
+    fn test_pack_errors() {
+        let e = pack!("C (n W)2", 1, 2, 'a', 3, '☺').unwrap_err();
+        let at = Location { offset: 6, item: 3, span: 5..6 };
+        let (pack_type, cause) = (Box::new(PackType::WideChar(Count::Exact(1))), Box::new(PackError::WideCharacter));
+        assert_eq!(e, PackError::Item { at, argument: Some(4), pack_type, cause });
+        assert_eq!(e.to_string(), "PackError: Characters above 255 need a UTF-8 string, from a template starting with `U` or holding `U0` \
+            with argument 4 at offset 6, in item 3 at 5..6 of the template");
+        assert_eq!(e.source().unwrap().to_string(), PackError::WideCharacter.to_string());
+        assert!(e.root_cause().source().is_none());
+
+        assert!(matches!(pack!("C X2", 1), Err(PackError::Item { argument: None, at: Location { item: 1, .. }, .. })));
+
+        let e = pack("C (n y)", [1, 2].map(PackableArg::from).into_iter()).unwrap_err();
+        let template = TemplateError { error: PackError::InvalidFormatCharacter, offset: 5, snippet: "y".to_string() };
+        assert_eq!(e, PackError::Template(Box::new(template)));
+        assert_eq!(e.to_string(), "PackError: Format character is not supported at offset 5: `y`");
+        assert_eq!(e.root_cause(), &PackError::InvalidFormatCharacter);
+        assert!(matches!(pack_with_abi("C (n y)", [1].map(PackableArg::from).into_iter(), Abi::LP64), Err(PackError::Template(e)) if e.offset == 5));
+
+        /// A name of at most 4 bytes.
+        struct Name(&'static str);
+
+        impl Packable for Name {
             fn pack(self: Box<Self>, pack_type: PackType) -> Result<Packed, PackError> {
-                match pack_type {
-                    PackType::StringNullPadded(Some(10)) => Ok(vec![0, 10]),
-                    PackType::UnsignedShort(Some(3)) => Ok(vec![33, 3]),
-                    PackType::SignedShort(None) => Ok(vec![44, 44]),
-                    _ => Err(PackError::InvalidFormatCharacter)
+                match (&pack_type, self.0.len()) {
+                    (PackType::StringNullPadded(_), 0..=4) => Box::new(self.0).pack(pack_type),
+                    (PackType::StringNullPadded(_), _) => Err(PackError::StringTooLong),
+                    _ => Err(PackError::WrongArgumentType),
                 }
             }
         }
-        let pack = pack("a[10]S3s", [10u16, 11u16, 12u16].map(|f| PackableArg { inner: Box::new(f) }).into_iter());
-        assert!(pack.is_ok());
-        assert!(pack.unwrap().eq(&[0, 10, 33, 3, 44, 44u8]));
+
+        let args = || [PackableArg::from(1), PackableArg::from(Name("much too long"))].into_iter();
+        let e = pack("C a4", args()).unwrap_err();
+        assert!(matches!(e, PackError::Item { argument: Some(1), ref at, .. } if at.span == (2..4)));
+        assert_eq!(e.root_cause(), &PackError::StringTooLong);
+        assert_eq!(pack("C n", args()).unwrap_err().root_cause(), &PackError::WrongArgumentType);
     }
-}
 
+    #[test]
+    fn test_unpack_errors() {
+        let template = Template::parse("a4 (C n/(w U0 a2)2)* %16C3 C0 x[N] @0").unwrap();
+        assert_eq!(template.items().iter().map(nodes).sum::<usize>(), template.spans().len());
+
+        let at = Location { offset: 7, item: 5, span: 8..9 };
+        assert_eq!(unpack("a4 C n/(w)", b"head\x01\x00\x01\xff\xff"), Err(UnpackError::Truncated { at: at.clone(), needed: 3, available: 2 }));
+        assert_eq!(
+            UnpackError::Truncated { at, needed: 3, available: 2 }.to_string(),
+            "UnpackError: Data is shorter then template requires, 3 bytes needed and 2 left at offset 7, in item 5 at 8..9 of the template",
+        );
+        let at = Location { offset: 1, item: 2, span: 2..3 };
+        assert_eq!(unpack("C w/a", &[[1].as_slice(), &[0xff; 19], &[0x7f]].concat()), Err(UnpackError::Overflow { at: at.clone() }));
+        assert_eq!(unpack_with_limit("C w/a", &[1, 0x81, 0], 100), Err(UnpackError::LengthOverLimit { at, length: 128, limit: 100 }));
+        let at = Location { offset: 2, item: 2, span: 4..6 };
+        assert_eq!(unpack("C C @9", &[1, 2]), Err(UnpackError::PositionOutsideOfData { at }));
+
+        let at = Location { offset: 2, item: 1, span: 2..4 };
+        let e = unpack!("n A3", b"\0\x01\xff\xfe\xfd").unwrap_err();
+        assert_eq!(e, UnpackError::Value { at, value: 1, cause: Box::new(UnpackError::InvalidUtf8) });
+        assert_eq!(e.to_string(), "UnpackError: Value is not a valid UTF-8 string for value 1 at offset 2, in item 1 at 2..4 of the template");
+        assert_eq!(e.root_cause(), &UnpackError::InvalidUtf8);
+        assert_eq!(e.source().unwrap().to_string(), UnpackError::InvalidUtf8.to_string());
+        // values of groups are located at the group
+        let e = unpack!("C (n A2)", [1, 0, 2, 0xff, 0xfe]).unwrap_err();
+        assert!(matches!(e, UnpackError::Value { value: 2, at: Location { item: 1, offset: 1, .. }, .. }));
+
+        #[derive(Unpack, Debug)]
+        struct Short {
+            #[pack("C")]
+            _kind: u8,
+            #[pack("n")]
+            _length: u8,
+        }
+
+        let at = Location { offset: 1, item: 1, span: 2..3 };
+        let cause = Box::new(UnpackError::ValueOutOfRange);
+        assert_eq!(Short::unpack(&[1, 1, 0]).unwrap_err(), UnpackError::Value { at, value: 1, cause });
+
+        let e = unpack("C (n y)", &[]).unwrap_err();
+        assert!(matches!(e, UnpackError::InvalidTemplate(PackError::Template(ref t)) if t.offset == 5));
+        assert!(matches!(unpack_with_abi("C y", &[], Abi::LP64), Err(UnpackError::InvalidTemplate(PackError::Template(t))) if t.offset == 2));
+    }
+
+    #[test]
+    fn test_unpack_ref() {
+        let packed = b"\x00\x03abcname\0pad  \x05\xff";
+        let values = unpack_ref("n/a* Z* A5 b3 C", packed).unwrap();
+        let [ref name, ref z, ref a, ref bits, ref number] = values[..] else { panic!("{:?}", values) };
+        let borrowed = <&[u8]>::try_from(name.clone()).unwrap();
+        assert_eq!(borrowed, b"abc");
+        assert!(packed.as_ptr_range().contains(&borrowed.as_ptr()));
+        assert_eq!(<&str>::try_from(z.clone()).unwrap(), "name");
+        assert_eq!(<&str>::try_from(a.clone()).unwrap(), "pad");
+        assert_eq!(bits, &UnpackedRef::Bytes(Cow::Owned(b"101".to_vec())));
+        assert!(matches!(<&[u8]>::try_from(bits.clone()), Err(UnpackError::IncompatibleValue)));
+        assert_eq!(number, &UnpackedRef::Unsigned(255));
+        assert_eq!(values.into_iter().map(UnpackedRef::into_owned).collect::<Vec<_>>(), unpack("n/a* Z* A5 b3 C", packed).unwrap());
+        assert!(matches!(<&str>::try_from(unpack_ref("a", b"\xff").unwrap().remove(0)), Err(UnpackError::InvalidUtf8)));
+    }
+}
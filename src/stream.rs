@@ -0,0 +1,340 @@
+//! [`pack_to`] and [`unpack_from`], packing into an [`io::Write`] and unpacking from an [`io::Read`]
+//! without the whole string in memory.
+use std::io::{self, Read, Write};
+
+use crate::{compiled, is_utf8, nodes, pack_output, position, unpack_checked, unpack_private, Count, Cursor, Interrupted, Output, Tree,
+            PackError, PackType, PackableArg, Packed, Template, Unpackable, Unpacked, UnpackedRef, UnpackError, DEFAULT_MAX_LENGTH};
+
+/// Packs the arguments according to the template into `writer`, see [`pack()`](crate::pack()), and returns the number of bytes written.
+///
+/// The string is written in chunks as the arguments are packed, so it never is in memory as a whole,
+/// except for templates with `X`, `X!`, `@` or `.`: they may go back over what was packed and are written at the end.
+/// Errors of the template or of the arguments are [`io::ErrorKind::InvalidInput`] errors holding the [`PackError`].
+pub fn pack_to<'a, T, W: Write>(template: &str, args: T, mut writer: W) -> io::Result<usize> where
+    T: Iterator<Item=PackableArg<'a>> {
+    let template = compiled(template).map_err(invalid_input)?;
+    write_packed(&template, args, &mut writer)
+}
+
+/// Unpacks a single record of the template from `reader`, see [`unpack()`](crate::unpack()).
+///
+/// Only the bytes the template reads are taken from `reader`, so calling it in a loop unpacks one record after another:
+/// `Z*` reads up to its null byte, `/` reads its length then as much as it tells, varints read up to their last byte.
+/// Formats reading whatever is left (`a*`, `C*`, `(nC)*`, `@*`, `u`, `U`) and templates of UTF-8 strings read `reader` to its end.
+/// `Z*` and varints read a byte at a time, wrap files and sockets in a [`io::BufReader`].
+///
+/// Data ending early is an [`io::ErrorKind::UnexpectedEof`] error, an invalid template an [`io::ErrorKind::InvalidInput`] error
+/// and data which can't be unpacked an [`io::ErrorKind::InvalidData`] error, all of them holding the [`UnpackError`].
+///
+/// ```
+/// use rust_pack::{unpack_from, Unpacked};
+///
+/// let mut records: &[u8] = b"\x02\x00first\x00\x01\x00second\x00";
+/// assert_eq!(unpack_from("vZ*", &mut records).unwrap()[1], Unpacked::Bytes(b"first".to_vec()));
+/// assert_eq!(unpack_from("vZ*", &mut records).unwrap()[1], Unpacked::Bytes(b"second".to_vec()));
+/// assert!(records.is_empty());
+/// ```
+pub fn unpack_from<R: Read>(template: &str, mut reader: R) -> io::Result<Vec<Unpacked>> {
+    let template = compiled(template).map_err(|e| unpack_error(UnpackError::InvalidTemplate(e)))?;
+    read_unpacked(&template, &mut reader, DEFAULT_MAX_LENGTH)
+}
+
+pub(crate) fn write_packed<'a, T>(template: &Template, args: T, writer: &mut dyn Write) -> io::Result<usize> where
+    T: Iterator<Item=PackableArg<'a>> {
+    // `X`, `X!`, `@` and `.` may go back over what was packed, such templates are written whole at the end
+    let output = Chunks { packed: Packed::with_capacity(CHUNK_SIZE), written: 0, writer, streaming: !moves_back(template.items()) };
+    let output = match pack_output(Tree::new(template), args, output) {
+        Ok(output) => output,
+        Err(Interrupted::Pack(e)) => return Err(invalid_input(e)),
+        Err(Interrupted::Write(e)) => return Err(e),
+    };
+    output.writer.write_all(&output.packed)?;
+    Ok(output.len())
+}
+
+/// Whether the template holds a format moving the position backward.
+fn moves_back(template: &[PackType]) -> bool {
+    template.iter().any(|p| match p {
+        PackType::Group(items, _) => moves_back(items),
+        PackType::LengthPrefixed(length, item) => moves_back(core::slice::from_ref(length)) || moves_back(core::slice::from_ref(item)),
+        PackType::BackUpByte(_) | PackType::BackUpByteAlign(_) | PackType::AbsolutePosition(_) | PackType::ValuePosition(_) => true,
+        _ => false,
+    })
+}
+
+/// Size of the chunks [`pack_to`] writes.
+const CHUNK_SIZE: usize = 4096;
+
+/// Output of [`pack_to`], writing the string a chunk at a time.
+struct Chunks<'w> {
+    /// What is packed and not written yet.
+    packed: Packed,
+    written: usize,
+    writer: &'w mut dyn Write,
+    /// Whether chunks are written along the way rather than at the end.
+    streaming: bool,
+}
+
+impl Chunks<'_> {
+    fn offset(&self, position: usize) -> Result<usize, PackError> {
+        position.checked_sub(self.written).ok_or(PackError::PositionOutsideOfString)
+    }
+}
+
+impl Output for Chunks<'_> {
+    fn len(&self) -> usize {
+        self.written + self.packed.len()
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> Result<(), PackError> {
+        self.packed.put(bytes)
+    }
+
+    fn put_len(&mut self, len: usize) -> Result<(), PackError> {
+        let len = self.offset(len)?;
+        self.packed.put_len(len)
+    }
+
+    fn room(&mut self, size: usize) -> Result<&mut [u8], PackError> {
+        self.packed.room(size)
+    }
+
+    fn tail(&mut self, start: usize) -> Result<&mut [u8], PackError> {
+        let start = self.offset(start)?;
+        self.packed.tail(start)
+    }
+
+    fn flush(&mut self) -> Result<(), Interrupted> {
+        if self.streaming && self.packed.len() >= CHUNK_SIZE {
+            self.writer.write_all(&self.packed).map_err(Interrupted::Write)?;
+            self.written += self.packed.len();
+            self.packed.clear();
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn read_unpacked(template: &Template, reader: &mut dyn Read, max_length: usize) -> io::Result<Vec<Unpacked>> {
+    read_record(template, reader, max_length)?.0.map(|(values, _)| values).map_err(unpack_error)
+}
+
+/// Values of a record with where the template ended in the data, or why it couldn't be unpacked.
+pub(crate) type Record = Result<(Vec<Unpacked>, usize), UnpackError>;
+
+/// Reads a record of the template, errors of `reader` are kept apart from those of the data.
+/// The values come with where the template ended in what was read, which may be before its end.
+/// Also tells whether `reader` ended before what the template reads: before the null byte of `Z*`,
+/// the last byte of a varint or a position past what was read.
+pub(crate) fn read_record(template: &Template, reader: &mut dyn Read, max_length: usize) -> io::Result<(Record, bool)> {
+    let mut source = Source { reader, data: Vec::new(), max_length, ended: false };
+    if is_utf8(&Tree::new(template)) {
+        // counts of characters don't tell how many bytes to read
+        source.read_all()?;
+        let mut locations = Vec::new();
+        let values = unpack_checked(template, &source.data, max_length, &[], false, Some(&mut locations));
+        let end = locations.last().map_or(0, |end| end.offset);
+        return Ok((values.map(|values| (values.into_iter().map(UnpackedRef::into_owned).collect(), end)), false));
+    }
+    let mut result = Vec::with_capacity(template.items().len());
+    let (mut position, mut index) = (0, 0);
+    for pack_type in template.items() {
+        source.fill(pack_type, position, 0)?;
+        let mut cursor = Cursor {
+            data: &source.data, position, groups: vec![0], max_length, utf8: false, characters: false, spans: template.spans(),
+        };
+        // values can't borrow the data, which grows with every item
+        let mut values = Vec::new();
+        if let Err(e) = unpack_private(pack_type, index, &mut cursor, &mut values) {
+            return Ok((Err(e), source.ended));
+        }
+        result.extend(values.into_iter().map(UnpackedRef::into_owned));
+        position = cursor.position;
+        index += nodes(pack_type);
+    }
+    Ok((Ok((result, position)), source.ended))
+}
+
+pub(crate) fn invalid_input(e: PackError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e)
+}
+
+pub(crate) fn unpack_error(e: UnpackError) -> io::Error {
+    let kind = match e {
+        UnpackError::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+        UnpackError::InvalidTemplate(_) => io::ErrorKind::InvalidInput,
+        _ => io::ErrorKind::InvalidData,
+    };
+    io::Error::new(kind, e)
+}
+
+/// The data read so far for [`unpack_from`].
+struct Source<'r> {
+    reader: &'r mut dyn Read,
+    data: Vec<u8>,
+    max_length: usize,
+    /// Whether `reader` ended before what was to be read.
+    ended: bool,
+}
+
+impl Source<'_> {
+    /// Reads what the format reads from `at`, `group` being the start of its innermost group, and returns where it ends.
+    /// Data ending early is left for unpacking to report.
+    fn fill(&mut self, pack_type: &PackType, at: usize, group: usize) -> io::Result<usize> {
+        let end = match pack_type {
+            PackType::Group(items, Count::Exact(count)) => {
+                let mut end = at;
+                for _ in 0..*count {
+                    let start = end;
+                    for item in items {
+                        end = self.fill(item, end, start)?;
+                    }
+                }
+                return Ok(end);
+            }
+            PackType::LengthPrefixed(length, item) => {
+                let end = self.fill(length, at, group)?;
+                let mut cursor = Cursor {
+                    data: &self.data, position: at, groups: vec![group], max_length: self.max_length, utf8: false, characters: false, spans: &[],
+                };
+                let mut lengths = Vec::with_capacity(1);
+                let count = unpack_private(length, 0, &mut cursor, &mut lengths).ok()
+                    .and_then(|_| lengths.pop())
+                    .and_then(|length| usize::unpack(length.into_owned()).ok())
+                    .filter(|count| *count <= self.max_length);
+                return match count {
+                    Some(count) => self.fill(&item.with_count(Count::Exact(count)), end, group),
+                    None => Ok(end),
+                };
+            }
+            PackType::Checksum(_, item) => return self.fill(item, at, group),
+            PackType::CharacterMode | PackType::ByteMode => return Ok(at),
+            PackType::AscizNullPadded(Count::Star) => return self.read_through(at, 1, |b| b == 0),
+            PackType::AbsolutePosition(Count::Star) | PackType::Uuencoded(_) | PackType::UnicodeChar(_) => return self.read_all(),
+            p if p.is_position() => position(p, at, group, at).unwrap_or(at),
+            PackType::NullByte(c) => at.saturating_add(c.or(0)),
+            p if p.count() == Count::Star => return self.read_all(),
+            p if p.is_varint() => return self.read_through(at, p.count().or(0), |b| b & 0x80 == 0),
+            PackType::StringNullPadded(c) | PackType::AsciiNullPadded(c) | PackType::AscizNullPadded(c) => at.saturating_add(c.or(0)),
+            PackType::BitStringAscending(c) | PackType::BitStringDescending(c) => at.saturating_add(c.or(0).div_ceil(8)),
+            PackType::HexStringLowFirst(c) | PackType::HexStringHighFirst(c) => at.saturating_add(c.or(0).div_ceil(2)),
+            PackType::WideChar(c) => at.saturating_add(c.or(0)),
+            p => match p.size() {
+                Some(size) => at.saturating_add(size.saturating_mul(p.count().or(0))),
+                None => return self.read_all(),
+            },
+        };
+        self.read_to(end)?;
+        Ok(end)
+    }
+
+    fn read_to(&mut self, end: usize) -> io::Result<()> {
+        if let Some(missing) = end.checked_sub(self.data.len()) {
+            self.ended |= self.reader.take(missing as u64).read_to_end(&mut self.data)? < missing;
+        }
+        Ok(())
+    }
+
+    /// Reads from `at` through the `count`th byte which is `last`, and returns the position after it.
+    fn read_through(&mut self, at: usize, count: usize, last: impl Fn(u8) -> bool) -> io::Result<usize> {
+        let mut end = at;
+        for _ in 0..count {
+            loop {
+                if end >= self.data.len() && (end > self.data.len() || self.reader.take(1).read_to_end(&mut self.data)? == 0) {
+                    self.ended = true;
+                    return Ok(end);
+                }
+                end += 1;
+                if last(self.data[end - 1]) {
+                    break;
+                }
+            }
+        }
+        Ok(end)
+    }
+
+    fn read_all(&mut self) -> io::Result<usize> {
+        self.reader.read_to_end(&mut self.data)?;
+        Ok(self.data.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack;
+
+    /// Remembers the size of every write.
+    #[derive(Default)]
+    struct Writes(Vec<u8>, Vec<usize>);
+
+    impl Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.extend_from_slice(buf);
+            self.1.push(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn args(count: usize) -> impl Iterator<Item=PackableArg<'static>> {
+        (0..count).flat_map(|i| [PackableArg::from(i as u16), PackableArg::from("abc")])
+    }
+
+    #[test]
+    fn test_pack_to() {
+        let mut writes = Writes::default();
+        assert_eq!(pack_to("(nA4)*", args(5000), &mut writes).unwrap(), 30000);
+        assert_eq!(writes.0, pack("(nA4)*", args(5000)).unwrap());
+        assert!(writes.1.len() > 1 && writes.1.iter().all(|w| *w < 5000));
+
+        // the length is written once the sequence is packed
+        let mut writes = Writes::default();
+        pack_to("N/(nA4)", args(5000), &mut writes).unwrap();
+        assert_eq!(writes.0, pack("N/(nA4)", args(5000)).unwrap());
+        assert_eq!(writes.1, [30004]);
+
+        let mut writes = Writes::default();
+        pack_to("(nA4)* X2", args(5000), &mut writes).unwrap();
+        assert_eq!(writes.1, [29998]);
+
+        let mut writes = Writes::default();
+        assert_eq!(pack_to("(nA4)999 x!8 C", args(999).chain([PackableArg::from(1u8)]), &mut writes).unwrap(), 6001);
+        assert_eq!(writes.0, pack("(nA4)999 x!8 C", args(999).chain([PackableArg::from(1u8)])).unwrap());
+
+        let e = pack_to("nA4", args(2), Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(e.into_inner().unwrap().downcast::<PackError>().ok().map(|e| *e), Some(PackError::LeftArgumentIsMissingForTemplate));
+        let mut full = [0u8; 100];
+        assert_eq!(pack_to("(nA4)*", args(5000), &mut full[..]).unwrap_err().kind(), io::ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn test_unpack_from() {
+        let record = |n: u16, name: &str| pack("n/a* Z* w C2", [
+            PackableArg::from(name), PackableArg::from(name), PackableArg::from(n as u32 * 1000), PackableArg::from(1), PackableArg::from(2),
+        ].into_iter()).unwrap();
+        let data = [record(1, "one"), record(2, "two"), record(3, "three")].concat();
+        let mut reader = data.as_slice();
+        for (n, name) in [(1, "one"), (2, "two"), (3, "three")] {
+            assert_eq!(unpack_from("n/a* Z* w C2", &mut reader).unwrap(), [
+                Unpacked::Bytes(name.into()), Unpacked::Bytes(name.into()), Unpacked::Unsigned(n * 1000), Unpacked::Unsigned(1), Unpacked::Unsigned(2),
+            ]);
+        }
+        assert_eq!(unpack_from("n/a* Z* w C2", &mut reader).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut reader: &[u8] = b"\x00\x02\x01\x02\x00\x01\x03\x00\x04\x05rest";
+        assert_eq!(unpack_from("(n/C*)2", &mut reader).unwrap().len(), 3);
+        assert_eq!(reader, b"\x00\x04\x05rest");
+        assert_eq!(unpack_from("x2 @1 C", &mut reader).unwrap(), [Unpacked::Unsigned(4)]);
+        assert_eq!(reader, b"\x05rest");
+        assert_eq!(unpack_from("x a*", &mut reader).unwrap(), [Unpacked::Bytes(b"rest".to_vec())]);
+        assert!(reader.is_empty());
+
+        let mut reader: &[u8] = b"\xff\xff\xff\xff";
+        assert_eq!(unpack_from("N/a*", &mut reader).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(unpack_from("N/y", &mut reader).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,285 @@
+//! [`Template`], a template parsed once into a tree of [`PackType`]s to be used over and over.
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::ops::Range;
+use core::str::FromStr;
+#[cfg(feature = "cache")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "cache")]
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+
+#[cfg(feature = "std")]
+use crate::stream::{read_unpacked, write_packed};
+use rust_pack_syntax::parse;
+use crate::{apply_abi, pack_private, pack_source, unpack_borrowed, unpack_checked, unpack_template, Abi, PackError, PackType, TemplateError, Packable, PackableArg, Packed, Unpacked, UnpackedRef, UnpackError, DEFAULT_MAX_LENGTH};
+
+/// A parsed template: its formats as a tree of [`PackType`]s, and where each of them is in the source.
+///
+/// Formats may be separated by whitespace and `#` comments running to the end of the line,
+/// modifiers and counts follow their format character immediately.
+/// Besides a number and `*`, a count can be `[N]`, or `[template]` for the size in bytes of a template of fixed size
+/// (with the native sizes of `i`, `j`, `s!`...), so `x[N]` skips the size of an `N`:
+///
+/// ```
+/// use rust_pack::{Count, PackType, Template};
+///
+/// let template = Template::parse("n/a* # name\n x[N] C[2]").unwrap();
+/// assert_eq!(template.items()[1], PackType::NullByte(Count::Exact(4)));
+/// assert_eq!(&template.source()[template.spans()[3].clone()], "x[N]");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    source: String,
+    items: Vec<PackType>,
+    spans: Vec<Range<usize>>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, TemplateError> {
+        let (items, spans) = parse(source, Some(Abi::NATIVE))?;
+        Ok(Template { source: source.to_string(), items, spans })
+    }
+
+    /// Same as [`Template::parse`], with the native formats, and the `[template]` counts holding them, sized after `abi`.
+    pub(crate) fn parse_with_abi(source: &str, abi: Abi) -> Result<Template, PackError> {
+        let (mut items, spans) = parse(source, Some(abi))?;
+        apply_abi(&mut items, abi)?;
+        Ok(Template { source: source.to_string(), items, spans })
+    }
+
+    /// The formats of the template, groups and `/` holding theirs.
+    pub fn items(&self) -> &[PackType] {
+        &self.items
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Byte range in the source of every item, in the order they are met walking the tree of [`Template::items`]:
+    /// a group before its items, a `/` before its length and the item following it, a `%` before its format.
+    pub fn spans(&self) -> &[Range<usize>] {
+        &self.spans
+    }
+
+    /// Parses the template once to [`Template::pack`] and [`Template::unpack`] with it as many times as needed.
+    ///
+    /// ```
+    /// use rust_pack::{PackableArg, Template};
+    ///
+    /// let record = Template::compile("nCZ*").unwrap();
+    /// for name in ["one", "two"] {
+    ///     let args = [PackableArg::from(513u16), PackableArg::from(7u8), PackableArg::from(name)];
+    ///     let packed = record.pack(args.into_iter()).unwrap();
+    ///     assert_eq!(record.unpack(&packed).unwrap().len(), 3);
+    /// }
+    /// ```
+    pub fn compile(source: &str) -> Result<Template, TemplateError> {
+        Template::parse(source)
+    }
+
+    /// Same as [`pack()`](crate::pack()) with this template.
+    pub fn pack<'a, T>(&self, args: T) -> Result<Packed, PackError> where
+        T: Iterator<Item=PackableArg<'a>> {
+        pack_private(self, args)
+    }
+
+    /// Same as [`unpack()`](crate::unpack()) with this template.
+    pub fn unpack(&self, packed: &[u8]) -> Result<Vec<Unpacked>, UnpackError> {
+        unpack_template(self, packed, DEFAULT_MAX_LENGTH)
+    }
+
+    /// Same as [`Template::unpack`], failing with [`UnpackError::TrailingData`] when the template doesn't read the data to its end.
+    pub fn unpack_exact(&self, packed: &[u8]) -> Result<Vec<Unpacked>, UnpackError> {
+        Ok(unpack_checked(self, packed, DEFAULT_MAX_LENGTH, &[], true, None)?.into_iter().map(UnpackedRef::into_owned).collect())
+    }
+
+    /// Same as [`Template::unpack`], failing with [`UnpackError::Mismatch`] unless the value at each index of `expected`
+    /// is the one paired with it: magic numbers, versions, checksums computed beforehand...
+    ///
+    /// ```
+    /// use rust_pack::{Template, Unpacked, UnpackError};
+    ///
+    /// let header = Template::compile("a4 C n/a*").unwrap();
+    /// let expected = [(0, Unpacked::Bytes(b"RPK1".to_vec()))];
+    /// assert_eq!(header.unpack_expecting(b"RPK1\x07\x00\x02ok", &expected).unwrap().len(), 3);
+    /// let e = header.unpack_expecting(b"RPK2\x07\x00\x02ok", &expected).unwrap_err();
+    /// assert!(matches!(e, UnpackError::Mismatch { at, .. } if at.offset == 0 && at.span == (0..2)));
+    /// ```
+    pub fn unpack_expecting(&self, packed: &[u8], expected: &[(usize, Unpacked)]) -> Result<Vec<Unpacked>, UnpackError> {
+        Ok(unpack_checked(self, packed, DEFAULT_MAX_LENGTH, expected, false, None)?.into_iter().map(UnpackedRef::into_owned).collect())
+    }
+
+    /// Same as [`unpack_ref`](crate::unpack_ref) with this template.
+    pub fn unpack_ref<'a>(&self, packed: &'a [u8]) -> Result<Vec<UnpackedRef<'a>>, UnpackError> {
+        unpack_borrowed(self, packed, DEFAULT_MAX_LENGTH)
+    }
+
+    /// Same as [`unpack_with_limit`](crate::unpack_with_limit) with this template.
+    pub fn unpack_with_limit(&self, packed: &[u8], max_length: usize) -> Result<Vec<Unpacked>, UnpackError> {
+        unpack_template(self, packed, max_length)
+    }
+
+    /// Same as [`pack_into`](crate::pack_into) with this template.
+    pub fn pack_into<'a, T>(&self, args: T, buffer: &mut [u8]) -> Result<usize, PackError> where
+        T: Iterator<Item=&'a dyn Packable> {
+        pack_source(&self.source, args, buffer)
+    }
+
+    /// Same as [`pack_to`](crate::pack_to) with this template.
+    #[cfg(feature = "std")]
+    pub fn pack_to<'a, T, W: Write>(&self, args: T, mut writer: W) -> io::Result<usize> where
+        T: Iterator<Item=PackableArg<'a>> {
+        write_packed(self, args, &mut writer)
+    }
+
+    /// Same as [`unpack_from`](crate::unpack_from) with this template.
+    #[cfg(feature = "std")]
+    pub fn unpack_from<R: Read>(&self, mut reader: R) -> io::Result<Vec<Unpacked>> {
+        read_unpacked(self, &mut reader, DEFAULT_MAX_LENGTH)
+    }
+}
+
+/// Templates compiled by [`pack()`](crate::pack()), [`unpack()`](crate::unpack()) and [`unpack_with_limit`](crate::unpack_with_limit),
+/// by source.
+#[cfg(feature = "cache")]
+static CACHE: OnceLock<RwLock<HashMap<String, Arc<Template>>>> = OnceLock::new();
+
+/// Number of templates [`CACHE`] holds before starting over, so generated templates can't grow it forever.
+#[cfg(feature = "cache")]
+const CACHE_CAPACITY: usize = 1024;
+
+/// The compiled template from the cache, compiled and cached on the first call.
+#[cfg(feature = "cache")]
+pub(crate) fn cached(source: &str) -> Result<Arc<Template>, TemplateError> {
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some(template) = cache.read().unwrap_or_else(PoisonError::into_inner).get(source) {
+        return Ok(template.clone());
+    }
+    let template = Arc::new(Template::compile(source)?);
+    let mut cache = cache.write().unwrap_or_else(PoisonError::into_inner);
+    if cache.len() >= CACHE_CAPACITY {
+        cache.clear();
+    }
+    cache.insert(source.to_string(), template.clone());
+    Ok(template)
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Template::parse(source)
+    }
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::error::Error;
+    use crate::{Count, Endianness, Location};
+
+    fn error(template: &str) -> (PackError, usize, String) {
+        let e = Template::parse(template).unwrap_err();
+        (e.error, e.offset, e.snippet)
+    }
+
+    #[test]
+    fn test_parse() {
+        let template = Template::parse("a[10] S3 # a comment (\n\ts!<2 (n C)2").unwrap();
+        assert_eq!(template.items(), [
+            PackType::StringNullPadded(Count::Exact(10)),
+            PackType::UnsignedShort(Count::Exact(3), Endianness::Native),
+            PackType::NativeSignedShort(Count::Exact(2), Endianness::Little),
+            PackType::Group(vec![PackType::UnsignedShortBE(Count::Exact(1)), PackType::UnsignedChar(Count::Exact(1))], Count::Exact(2)),
+        ]);
+        assert_eq!("x[N] x[(n C)2] C[2] @[a3 x5]".parse::<Template>().unwrap().items(), [
+            PackType::NullByte(Count::Exact(4)),
+            PackType::NullByte(Count::Exact(6)),
+            PackType::UnsignedChar(Count::Exact(2)),
+            PackType::AbsolutePosition(Count::Exact(8)),
+        ]);
+        let template = Template::parse("n / a* (C s)2 %8C").unwrap();
+        let spans = template.spans().iter().map(|s| &template.source()[s.clone()]).collect::<Vec<_>>();
+        assert_eq!(spans, ["n / a*", "n", "a*", "(C s)2", "C", "s", "%8C", "C"]);
+        assert_eq!(template.to_string(), "n / a* (C s)2 %8C");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error("n C, s"), (PackError::InvalidFormatCharacter, 3, ",".to_string()));
+        assert_eq!(error("n 2"), (PackError::EmptyFormatCharacter, 2, "2".to_string()));
+        assert_eq!(error("C (n C"), (PackError::UnbalancedParentheses, 2, "(".to_string()));
+        assert_eq!(error("C n)"), (PackError::UnbalancedParentheses, 3, ")".to_string()));
+        assert_eq!(error("C l<>2"), (PackError::InvalidFormatModifier, 2, "l<>2".to_string()));
+        assert_eq!(error("Cé"), (PackError::InvalidFormatCharacter, 1, "é".to_string()));
+        assert_eq!(error("  # nothing"), (PackError::EmptyTemplate, 0, String::new()));
+        assert_eq!(error("x[a*]"), (PackError::InvalidFormatLengthArgument, 1, "[a*]".to_string()));
+        assert_eq!(error("x[N"), (PackError::InvalidFormatLengthArgument, 1, "[N".to_string()));
+        assert_eq!(error("a* / C"), (PackError::InvalidLengthItem, 0, "a*".to_string()));
+        assert_eq!(error("n/X"), (PackError::InvalidLengthItem, 2, "X".to_string()));
+        assert_eq!(error("C %8a"), (PackError::InvalidChecksum, 2, "%8a".to_string()));
+        let e = Template::parse("n C, s").unwrap_err();
+        assert_eq!(e.to_string(), "PackError: Format character is not supported at offset 3: `,`");
+        assert!(e.source().is_some());
+    }
+
+    #[test]
+    fn test_compile() {
+        fn shareable<T: Send + Sync + Clone>(_: &T) {}
+        let template = Template::compile("n/a* C").unwrap();
+        shareable(&template);
+        let packed = template.pack([PackableArg::from("abc"), PackableArg::from(7u8)].into_iter()).unwrap();
+        assert_eq!(packed, b"\0\x03abc\x07");
+        let clone = template.clone();
+        std::thread::spawn(move || {
+            assert_eq!(clone.unpack(&packed).unwrap(), [Unpacked::Bytes(b"abc".to_vec()), Unpacked::Unsigned(7)]);
+        }).join().unwrap();
+        assert!(template.unpack_with_limit(b"\0\x03abc\x07", 2).is_err());
+        let at = Location { offset: 5, item: 3, span: 5..6 };
+        assert_eq!(template.pack([PackableArg::from("abc")].into_iter()), Err(PackError::Item {
+            at, argument: Some(1), pack_type: Box::new(PackType::UnsignedChar(Count::Exact(1))), cause: Box::new(PackError::RightArgumentIsMissingForTemplate),
+        }));
+        #[cfg(feature = "std")]
+        {
+            let mut written = Vec::new();
+            template.pack_to([PackableArg::from("abc"), PackableArg::from(7u8)].into_iter(), &mut written).unwrap();
+            assert_eq!(template.unpack_from(written.as_slice()).unwrap(), [Unpacked::Bytes(b"abc".to_vec()), Unpacked::Unsigned(7)]);
+        }
+    }
+
+    #[test]
+    fn test_checks() {
+        let header = Template::compile("a4 (C n)").unwrap();
+        let packed = b"RPK1\x01\x02\x03";
+        assert_eq!(header.unpack_exact(packed).unwrap(), header.unpack(packed).unwrap());
+        let at = Location { offset: 7, item: 1, span: 3..8 };
+        assert_eq!(header.unpack_exact(b"RPK1\x01\x02\x03\x04\x05"), Err(UnpackError::TrailingData { at, remaining: 2 }));
+
+        let expected = [(0, Unpacked::Bytes(b"RPK1".to_vec())), (2, Unpacked::Unsigned(0x0203))];
+        assert_eq!(header.unpack_expecting(packed, &expected).unwrap().len(), 3);
+        let at = Location { offset: 4, item: 1, span: 3..8 };
+        assert_eq!(header.unpack_expecting(b"RPK1\x01\x02\x04", &expected), Err(UnpackError::Mismatch {
+            at, expected: Unpacked::Unsigned(0x0203), actual: Unpacked::Unsigned(0x0204),
+        }));
+        let (at, cause) = (Location { offset: 7, item: 1, span: 3..8 }, Box::new(UnpackError::NotEnoughData));
+        assert_eq!(header.unpack_expecting(packed, &[(3, Unpacked::Unsigned(0))]), Err(UnpackError::Value { at, value: 3, cause }));
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn test_cache() {
+        let template = cached("N/Z* # cached").unwrap();
+        assert!(Arc::ptr_eq(&template, &cached("N/Z* # cached").unwrap()));
+        assert_eq!(cached("N/X").unwrap_err().error, PackError::InvalidLengthItem);
+    }
+}